@@ -0,0 +1,664 @@
+//! Measurements of the structure of a grid's aggregate
+use rustfft::{num_complex::Complex, FftDirection, FftPlanner};
+
+use crate::grid::Grid;
+
+/// Least squares fit of a line y = slope * x + intercept
+#[derive(Clone, Copy, Debug)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// Coefficient of determination. 1.0 means the points are exactly on the line
+    pub r_squared: f64,
+}
+
+/// Fit a line to the points (xs[i], ys[i]). Returns None if there aren't at least 2 distinct x values
+pub fn linear_fit(xs: &[f64], ys: &[f64]) -> Option<LinearFit> {
+    assert_eq!(xs.len(), ys.len());
+    let n = xs.len() as f64;
+    if xs.len() < 2 {
+        return None;
+    }
+
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let mut sxx = 0.0;
+    let mut sxy = 0.0;
+    let mut syy = 0.0;
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        sxx += (x - mean_x).powi(2);
+        sxy += (x - mean_x) * (y - mean_y);
+        syy += (y - mean_y).powi(2);
+    }
+    if sxx == 0.0 {
+        return None; // vertical line
+    }
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    // if every y is the same the line fits perfectly
+    let r_squared = if syy == 0.0 {
+        1.0
+    } else {
+        (sxy * sxy) / (sxx * syy)
+    };
+
+    Some(LinearFit {
+        slope,
+        intercept,
+        r_squared,
+    })
+}
+
+/// Result of a box-counting measurement
+#[derive(Clone, Debug)]
+pub struct BoxCounting {
+    /// (box edge length, number of boxes containing at least one filled cell)
+    pub counts: Vec<(usize, usize)>,
+    /// Fit of log(count) against log(1 / box size). The slope is the box-counting dimension
+    pub fit: Option<LinearFit>,
+}
+
+impl BoxCounting {
+    /// The estimated box-counting (fractal) dimension, if there were enough box sizes to fit
+    pub fn dimension(&self) -> Option<f64> {
+        self.fit.map(|fit| fit.slope)
+    }
+}
+
+/// Box sizes to use when none are specified: powers of 2 from 1 up to a quarter of the shortest grid edge.
+/// Bigger boxes than that cover the whole aggregate with just a handful of boxes and skew the fit.
+pub fn default_box_sizes(grid: &Grid) -> Vec<usize> {
+    let max_size = std::cmp::max(std::cmp::min(grid.width, grid.height) / 4, 1);
+    std::iter::successors(Some(1_usize), |size| Some(size * 2))
+        .take_while(|size| *size <= max_size)
+        .collect()
+}
+
+/// Compute the box-counting dimension of the grid's filled cells.
+///
+/// The grid is covered with square boxes of each size, starting from the top left corner, and the boxes that contain
+/// at least one filled cell are counted. For a fractal, count ~ size^-D, so D is the slope of log(count) against
+/// log(1 / size).
+pub fn box_counting(grid: &Grid, sizes: &[usize]) -> BoxCounting {
+    let mut counts = Vec::new();
+    for &size in sizes {
+        assert!(size > 0, "box size must be at least 1");
+        let boxes_x = grid.width.div_ceil(size);
+        let boxes_y = grid.height.div_ceil(size);
+        let mut occupied = vec![false; boxes_x * boxes_y];
+        for (idx, cell) in grid.cells.iter().enumerate() {
+            if cell.filled {
+                let (x, y) = (idx % grid.width, idx / grid.width);
+                occupied[x / size + (y / size) * boxes_x] = true;
+            }
+        }
+        counts.push((size, occupied.iter().filter(|&&o| o).count()));
+    }
+
+    // empty boxes can't go on a log scale
+    let (xs, ys): (Vec<f64>, Vec<f64>) = counts
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(size, count)| ((1.0 / *size as f64).ln(), (*count as f64).ln()))
+        .unzip();
+
+    BoxCounting {
+        counts,
+        fit: linear_fit(&xs, &ys),
+    }
+}
+
+/// Mass–radius statistics of the particles that stick during a simulation, tracked incrementally so the grid never
+/// has to be rescanned. Seeds aren't included, only the mass that grew.
+#[derive(Clone, Debug, Default)]
+pub struct MassRadius {
+    count: usize,
+    sum_x: f64,
+    sum_y: f64,
+    /// Sum of x² + y²
+    sum_sq: f64,
+    /// Largest distance from the grid center any particle has stuck at
+    max_radius: usize,
+    /// (number of particles, radius of gyration) recorded every time the number of particles changed
+    series: Vec<(usize, f64)>,
+}
+
+impl MassRadius {
+    /// Rebuild the statistics of a finished grid by adding its particles in the order they stuck
+    pub fn from_grid(grid: &Grid) -> Self {
+        let mut particles: Vec<(usize, usize)> = grid
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.filled && cell.id != 0)
+            .map(|(idx, cell)| (cell.id, idx))
+            .collect();
+        particles.sort_unstable();
+
+        let mut mass_radius = Self::default();
+        for (_, idx) in particles {
+            let (x, y) = (idx % grid.width, idx / grid.width);
+            mass_radius.add(x, y, grid.dist_to_center(x, y));
+        }
+        mass_radius
+    }
+
+    /// A particle stuck at (x, y), `dist` away from the grid center
+    pub fn add(&mut self, x: usize, y: usize, dist: usize) {
+        self.shift(x, y, 1.0);
+        self.count += 1;
+        self.max_radius = std::cmp::max(self.max_radius, dist);
+        self.series.push((self.count, self.radius_of_gyration()));
+    }
+
+    /// A particle at (x, y) detached. The maximum radius is the largest ever reached, so it doesn't shrink
+    pub fn remove(&mut self, x: usize, y: usize) {
+        self.shift(x, y, -1.0);
+        self.count -= 1;
+        self.series.push((self.count, self.radius_of_gyration()));
+    }
+
+    /// A particle hopped from `from` to `to`. The number of particles didn't change, so nothing is added to the series
+    pub fn move_particle(&mut self, from: (usize, usize), to: (usize, usize), dist: usize) {
+        self.shift(from.0, from.1, -1.0);
+        self.shift(to.0, to.1, 1.0);
+        self.max_radius = std::cmp::max(self.max_radius, dist);
+    }
+
+    fn shift(&mut self, x: usize, y: usize, sign: f64) {
+        let (x, y) = (x as f64, y as f64);
+        self.sum_x += sign * x;
+        self.sum_y += sign * y;
+        self.sum_sq += sign * (x * x + y * y);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Root mean square distance of the particles from their center of mass
+    pub fn radius_of_gyration(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        let (mean_x, mean_y) = (self.sum_x / n, self.sum_y / n);
+        // rounding can make this slightly negative for tiny clusters
+        (self.sum_sq / n - mean_x * mean_x - mean_y * mean_y)
+            .max(0.0)
+            .sqrt()
+    }
+
+    pub fn max_radius(&self) -> usize {
+        self.max_radius
+    }
+
+    pub fn series(&self) -> &[(usize, f64)] {
+        &self.series
+    }
+
+    /// Fit of log(Rg) against log(N). Rg ~ N^(1/D), so the slope is the scaling exponent 1/D.
+    /// The first few particles are skipped since Rg is dominated by the lattice there
+    pub fn fit(&self) -> Option<LinearFit> {
+        const MIN_PARTICLES: usize = 10;
+        let (xs, ys): (Vec<f64>, Vec<f64>) = self
+            .series
+            .iter()
+            .filter(|(n, rg)| *n >= MIN_PARTICLES && *rg > 0.0)
+            .map(|(n, rg)| ((*n as f64).ln(), rg.ln()))
+            .unzip();
+        linear_fit(&xs, &ys)
+    }
+
+    /// Write the N vs Rg series as CSV
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "particles,radius_of_gyration")?;
+        for (n, rg) in self.series.iter() {
+            writeln!(writer, "{},{}", n, rg)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Two-point density correlation at one distance
+#[derive(Clone, Copy, Debug)]
+pub struct CorrelationPoint {
+    /// Distance, in cells
+    pub r: usize,
+    /// Radially averaged density at distance r from a filled cell
+    pub correlation: f64,
+    /// Density at distance r along the lattice axes only
+    pub axial: f64,
+    /// Density at distance r along the diagonals only (the nearest lattice points to them)
+    pub diagonal: f64,
+}
+
+/// Density–density correlation function of a grid's filled cells
+#[derive(Clone, Debug)]
+pub struct Correlation {
+    pub points: Vec<CorrelationPoint>,
+}
+
+impl Correlation {
+    /// Fit of log C(r) against log r for r in [r_min, r_max]. C(r) ~ r^(D - 2), so the slope is D - 2
+    pub fn fit(&self, r_min: usize, r_max: usize) -> Option<LinearFit> {
+        let (xs, ys): (Vec<f64>, Vec<f64>) = self
+            .points
+            .iter()
+            .filter(|p| p.r >= r_min && p.r <= r_max && p.r > 0 && p.correlation > 0.0)
+            .map(|p| ((p.r as f64).ln(), p.correlation.ln()))
+            .unzip();
+        linear_fit(&xs, &ys)
+    }
+
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "r,correlation,axial,diagonal")?;
+        for p in self.points.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                p.r, p.correlation, p.axial, p.diagonal
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+/// In place 2D FFT of a row-major width x height buffer
+fn fft_2d(data: &mut [Complex<f64>], width: usize, height: usize, direction: FftDirection) {
+    let mut planner = FftPlanner::new();
+
+    // rows are contiguous, and process() handles every width-long chunk of the buffer
+    planner.plan_fft(width, direction).process(data);
+
+    // columns aren't, so transpose them into rows and back
+    let mut transposed = vec![Complex::new(0.0, 0.0); data.len()];
+    for y in 0..height {
+        for x in 0..width {
+            transposed[y + x * height] = data[x + y * width];
+        }
+    }
+    planner.plan_fft(height, direction).process(&mut transposed);
+    for y in 0..height {
+        for x in 0..width {
+            data[x + y * width] = transposed[y + x * height];
+        }
+    }
+}
+
+/// Compute the radially averaged two-point density correlation C(r) of the grid's filled cells.
+///
+/// C(r) is the average density of filled cells at distance r from a filled cell. The autocorrelation of the grid is
+/// computed with an FFT (zero padded so it doesn't wrap around), then averaged over all displacements that round to
+/// the same distance. Distances go up to half the shortest grid edge.
+pub fn correlation(grid: &Grid) -> Correlation {
+    let (width, height) = (grid.width, grid.height);
+    let (pad_width, pad_height) = (width * 2, height * 2);
+
+    let mut data = vec![Complex::new(0.0, 0.0); pad_width * pad_height];
+    let mut filled = 0_usize;
+    for (idx, cell) in grid.cells.iter().enumerate() {
+        if cell.filled {
+            data[idx % width + (idx / width) * pad_width] = Complex::new(1.0, 0.0);
+            filled += 1;
+        }
+    }
+
+    // autocorrelation = inverse FFT of the power spectrum
+    fft_2d(&mut data, pad_width, pad_height, FftDirection::Forward);
+    for value in data.iter_mut() {
+        *value = Complex::new(value.norm_sqr(), 0.0);
+    }
+    fft_2d(&mut data, pad_width, pad_height, FftDirection::Inverse);
+    // rustfft doesn't normalize
+    let normalization = (pad_width * pad_height) as f64;
+    // number of pairs of filled cells at displacement (dx, dy). Negative displacements wrapped around
+    let pairs = |dx: isize, dy: isize| -> f64 {
+        let x = dx.rem_euclid(pad_width as isize) as usize;
+        let y = dy.rem_euclid(pad_height as isize) as usize;
+        data[x + y * pad_width].re / normalization
+    };
+
+    let max_r = std::cmp::min(width, height) / 2;
+    let mut sums = vec![0.0; max_r + 1];
+    let mut displacements = vec![0_usize; max_r + 1];
+    let max_d = max_r as isize;
+    for dy in -max_d..=max_d {
+        for dx in -max_d..=max_d {
+            let r = ((dx * dx + dy * dy) as f64).sqrt().round() as usize;
+            if r <= max_r {
+                sums[r] += pairs(dx, dy);
+                displacements[r] += 1;
+            }
+        }
+    }
+
+    let per_particle = if filled == 0 {
+        0.0
+    } else {
+        1.0 / filled as f64
+    };
+    let points = (0..=max_r)
+        .map(|r| {
+            let ri = r as isize;
+            let axial = (pairs(ri, 0) + pairs(-ri, 0) + pairs(0, ri) + pairs(0, -ri)) / 4.0;
+            let d = (r as f64 / std::f64::consts::SQRT_2).round() as isize;
+            let diagonal = (pairs(d, d) + pairs(-d, d) + pairs(d, -d) + pairs(-d, -d)) / 4.0;
+            CorrelationPoint {
+                r,
+                correlation: sums[r] / displacements[r] as f64 * per_particle,
+                axial: axial * per_particle,
+                diagonal: diagonal * per_particle,
+            }
+        })
+        .collect();
+
+    Correlation { points }
+}
+
+/// Gliding-box lacunarity of a grid's filled cells at a range of box sizes
+#[derive(Clone, Debug)]
+pub struct Lacunarity {
+    /// (box edge length, lacunarity). 1 means the mass is spread evenly, larger values mean bigger gaps
+    pub points: Vec<(usize, f64)>,
+}
+
+impl Lacunarity {
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "box_size,lacunarity")?;
+        for (size, lacunarity) in self.points.iter() {
+            writeln!(writer, "{},{}", size, lacunarity)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Compute the gliding-box lacunarity Λ(r) = <M²> / <M>² for each box edge r in `sizes`, where M is the number of
+/// filled cells in an r x r box and the averages are over every position the box fits in the grid. Sizes that don't fit
+/// in the grid, or whose boxes are all empty, are left out.
+pub fn lacunarity(grid: &Grid, sizes: &[usize]) -> Lacunarity {
+    let (width, height) = (grid.width, grid.height);
+    // summed area table, with an extra row and column of zeros so box sums don't need bounds checks
+    let mut sums = vec![0_u64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            let filled = u64::from(grid.cells[x + y * width].filled);
+            sums[(x + 1) + (y + 1) * (width + 1)] =
+                filled + sums[x + (y + 1) * (width + 1)] + sums[(x + 1) + y * (width + 1)]
+                    - sums[x + y * (width + 1)];
+        }
+    }
+    let area = |x: usize, y: usize, size: usize| -> u64 {
+        let at = |x: usize, y: usize| sums[x + y * (width + 1)];
+        at(x + size, y + size) + at(x, y) - at(x + size, y) - at(x, y + size)
+    };
+
+    let mut points = Vec::new();
+    for &size in sizes {
+        assert!(size > 0, "box size must be at least 1");
+        if size > width || size > height {
+            continue;
+        }
+        let (mut sum, mut sum_sq) = (0.0, 0.0);
+        for y in 0..=height - size {
+            for x in 0..=width - size {
+                let mass = area(x, y, size) as f64;
+                sum += mass;
+                sum_sq += mass * mass;
+            }
+        }
+        if sum > 0.0 {
+            let boxes = ((width - size + 1) * (height - size + 1)) as f64;
+            points.push((size, (sum_sq / boxes) / (sum / boxes).powi(2)));
+        }
+    }
+    Lacunarity { points }
+}
+
+/// One Fourier harmonic of the angular mass distribution
+#[derive(Clone, Copy, Debug)]
+pub struct Harmonic {
+    pub n: usize,
+    /// |<e^(inθ)>| over all particles. 0 means no n-fold symmetry, 1 means all the mass is on n evenly spaced rays
+    pub amplitude: f64,
+    /// Phase of the harmonic, in radians. The n-fold mass peaks at angles (phase + 2πk) / n
+    pub phase: f64,
+}
+
+/// How the mass that grew is spread around the seeds
+#[derive(Clone, Debug)]
+pub struct AngularDistribution {
+    /// The point angles are measured around: the centroid of the seeds
+    pub center: (f64, f64),
+    /// Number of particles measured
+    pub particles: usize,
+    /// Fraction of the particles in each equal angular bin, starting at angle 0 (pointing right) and going
+    /// counterclockwise as drawn on screen
+    pub bins: Vec<f64>,
+    /// Harmonics 1 through the highest one requested
+    pub harmonics: Vec<Harmonic>,
+}
+
+impl AngularDistribution {
+    /// The strongest harmonic with n >= 2, which is the dominant n-fold symmetry. The first harmonic isn't a symmetry,
+    /// it measures how lopsided the aggregate is.
+    pub fn dominant_symmetry(&self) -> Option<Harmonic> {
+        self.harmonics
+            .iter()
+            .filter(|h| h.n >= 2)
+            .copied()
+            .max_by(|a, b| a.amplitude.total_cmp(&b.amplitude))
+    }
+
+    /// Write one row per angular bin: the bin's start and end angle in degrees and its fraction of the mass
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "angle_start,angle_end,mass")?;
+        let bin_width = 360.0 / self.bins.len() as f64;
+        for (i, mass) in self.bins.iter().enumerate() {
+            writeln!(
+                writer,
+                "{},{},{}",
+                i as f64 * bin_width,
+                (i + 1) as f64 * bin_width,
+                mass
+            )?;
+        }
+        writer.flush()
+    }
+
+    pub fn harmonics_to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "n,amplitude,phase")?;
+        for h in self.harmonics.iter() {
+            writeln!(writer, "{},{},{}", h.n, h.amplitude, h.phase)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Measure the angular distribution of the particles that stuck (seeds aren't included) around the centroid of the
+/// seeds, in `bins` equal bins, along with its Fourier harmonics 1 through `max_harmonic`.
+///
+/// On a square lattice the 4th harmonic picks up lattice anisotropy, since growth along the axes and diagonals differs.
+pub fn angular_distribution(grid: &Grid, bins: usize, max_harmonic: usize) -> AngularDistribution {
+    assert!(bins > 0, "need at least 1 angular bin");
+    let position = |idx: usize| ((idx % grid.width) as f64, (idx / grid.width) as f64);
+
+    let seeds: Vec<(f64, f64)> = (0..grid.cells.len())
+        .filter(|&idx| grid.cells[idx].filled && grid.cells[idx].id == 0)
+        .map(position)
+        .collect();
+    let center = if seeds.is_empty() {
+        (grid.width as f64 / 2.0, grid.height as f64 / 2.0)
+    } else {
+        let n = seeds.len() as f64;
+        (
+            seeds.iter().map(|p| p.0).sum::<f64>() / n,
+            seeds.iter().map(|p| p.1).sum::<f64>() / n,
+        )
+    };
+
+    // y points down on screen, so flip it to measure angles counterclockwise as drawn
+    let angles: Vec<f64> = (0..grid.cells.len())
+        .filter(|&idx| grid.cells[idx].filled && grid.cells[idx].id != 0)
+        .map(position)
+        .filter(|&(x, y)| (x, y) != center)
+        .map(|(x, y)| {
+            (center.1 - y)
+                .atan2(x - center.0)
+                .rem_euclid(std::f64::consts::TAU)
+        })
+        .collect();
+
+    let particles = angles.len();
+    let mut counts = vec![0_usize; bins];
+    for angle in angles.iter() {
+        let bin = (angle / std::f64::consts::TAU * bins as f64) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    let total = particles.max(1) as f64;
+
+    let harmonics = (1..=max_harmonic)
+        .map(|n| {
+            let (re, im) = angles.iter().fold((0.0, 0.0), |(re, im), angle| {
+                let phase = n as f64 * angle;
+                (re + phase.cos(), im + phase.sin())
+            });
+            let (re, im) = (re / total, im / total);
+            Harmonic {
+                n,
+                amplitude: re.hypot(im),
+                phase: im.atan2(re),
+            }
+        })
+        .collect();
+
+    AngularDistribution {
+        center,
+        particles,
+        bins: counts.into_iter().map(|c| c as f64 / total).collect(),
+        harmonics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+
+    /// A width x height grid with the cells for which `filled` returns true filled
+    fn grid_with(width: usize, height: usize, filled: impl Fn(usize, usize) -> bool) -> Grid {
+        let cells = (0..width * height)
+            .map(|idx| Particle {
+                filled: filled(idx % width, idx / width),
+                id: 0,
+            })
+            .collect();
+        Grid {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn fit_of_a_line() {
+        let xs = [0.0, 1.0, 2.0, 3.0];
+        let ys = [1.0, 3.0, 5.0, 7.0];
+        let fit = linear_fit(&xs, &ys).unwrap();
+        assert!((fit.slope - 2.0).abs() < 1e-12);
+        assert!((fit.intercept - 1.0).abs() < 1e-12);
+        assert!((fit.r_squared - 1.0).abs() < 1e-12);
+
+        // one point, or the same x every time, has no slope
+        assert!(linear_fit(&[1.0], &[1.0]).is_none());
+        assert!(linear_fit(&[2.0, 2.0], &[1.0, 3.0]).is_none());
+    }
+
+    #[test]
+    fn box_counting_dimensions() {
+        let sizes = [1, 2, 4, 8, 16];
+
+        let square = grid_with(64, 64, |_, _| true);
+        let dimension = box_counting(&square, &sizes).dimension().unwrap();
+        assert!((dimension - 2.0).abs() < 1e-9, "{}", dimension);
+
+        let line = grid_with(64, 64, |_, y| y == 32);
+        let dimension = box_counting(&line, &sizes).dimension().unwrap();
+        assert!((dimension - 1.0).abs() < 1e-9, "{}", dimension);
+
+        let empty = grid_with(64, 64, |_, _| false);
+        assert!(box_counting(&empty, &sizes).dimension().is_none());
+    }
+
+    #[test]
+    fn mass_radius_of_a_line() {
+        // Rg of n evenly spaced particles is sqrt((n² - 1) / 12), which scales as n
+        let mut mass_radius = MassRadius::default();
+        for x in 0..200 {
+            mass_radius.add(x, 0, x);
+        }
+        assert_eq!(mass_radius.count(), 200);
+        let expected = ((200.0_f64 * 200.0 - 1.0) / 12.0).sqrt();
+        assert!((mass_radius.radius_of_gyration() - expected).abs() < 1e-6);
+        let slope = mass_radius.fit().unwrap().slope;
+        assert!((slope - 1.0).abs() < 0.01, "{}", slope);
+
+        // moving the last particle back next to the others leaves the count alone but shrinks Rg
+        mass_radius.move_particle((199, 0), (0, 1), 1);
+        assert_eq!(mass_radius.count(), 200);
+        assert!(mass_radius.radius_of_gyration() < expected);
+
+        mass_radius.remove(0, 1);
+        assert_eq!(mass_radius.count(), 199);
+        let expected = ((199.0_f64 * 199.0 - 1.0) / 12.0).sqrt();
+        assert!((mass_radius.radius_of_gyration() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn correlation_of_known_shapes() {
+        // a lone particle only correlates with itself
+        let single = correlation(&grid_with(16, 16, |x, y| (x, y) == (8, 8)));
+        assert!((single.points[0].correlation - 1.0).abs() < 1e-9);
+        assert!(single.points[1..]
+            .iter()
+            .all(|p| p.correlation.abs() < 1e-9));
+
+        // in a full grid, the cells one step away along an axis are inside it for all but one column or row
+        let full = correlation(&grid_with(32, 32, |_, _| true));
+        assert_eq!(full.points.len(), 17);
+        assert!((full.points[0].correlation - 1.0).abs() < 1e-9);
+        assert!((full.points[1].axial - 31.0 / 32.0).abs() < 1e-9);
+        assert!((full.points[1].diagonal - (31.0 / 32.0_f64).powi(2)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lacunarity_of_known_shapes() {
+        // every box of a full grid holds the same mass
+        let full = lacunarity(&grid_with(16, 16, |_, _| true), &[1, 2, 4, 8, 32]);
+        assert_eq!(
+            full.points.iter().map(|p| p.0).collect::<Vec<_>>(),
+            [1, 2, 4, 8]
+        );
+        assert!(full.points.iter().all(|p| (p.1 - 1.0).abs() < 1e-12));
+
+        // for single cells, <M²> = <M> = the filled fraction, so Λ is 1 over it
+        let checkerboard = lacunarity(&grid_with(16, 16, |x, y| (x + y) % 2 == 0), &[1]);
+        assert!((checkerboard.points[0].1 - 2.0).abs() < 1e-12);
+
+        assert!(lacunarity(&grid_with(16, 16, |_, _| false), &[1, 2])
+            .points
+            .is_empty());
+    }
+}
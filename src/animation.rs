@@ -0,0 +1,416 @@
+//! Animations of an aggregate growing, as animated GIF or PNG (APNG)
+//!
+//! Frames either come from the simulation as it runs, one every K particles that stick, or from replaying the ids of a
+//! finished grid (see Dla::handle_timeline_changed). Replays work for any grid, including ones read from a file, but
+//! show particles where they ended up. Frames taken while growing show relaxation and detaching as they happened.
+//!
+//! Frames are encoded as they're taken, so only the last one is kept in memory however long the animation is.
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::dla::Dla;
+use crate::image;
+
+/// How to record an animation, and where frames taken while the simulation runs go
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    /// File to write the animation to. Files ending in .gif are GIFs, anything else is an animated PNG
+    pub file_name: String,
+    /// Number of frames when replaying a finished grid
+    pub frames: usize,
+    /// When set, take a frame every this many stuck particles while the simulation runs instead of replaying
+    pub every: Option<usize>,
+    /// Milliseconds each frame is shown for
+    pub delay: u16,
+    /// Each particle is a square this many pixels wide
+    pub scale: usize,
+    /// Where frames taken while the simulation runs are written, opened with the first one. Shared so that cloning the
+    /// simulation doesn't try to duplicate the open file
+    writer: Option<Arc<Mutex<AnimationWriter>>>,
+}
+
+impl Recorder {
+    pub fn new(
+        file_name: &str,
+        frames: usize,
+        every: Option<usize>,
+        delay: u16,
+        scale: usize,
+    ) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            frames,
+            every: every.filter(|&k| k > 0),
+            delay,
+            scale,
+            writer: None,
+        }
+    }
+
+    /// Does the recorder want a frame now that this many particles have stuck? Seeds don't count, only particles that
+    /// stuck while the simulation ran
+    pub fn wants_frame(&self, grown: usize) -> bool {
+        self.every.is_some_and(|k| grown.is_multiple_of(k))
+    }
+
+    /// Add a frame of the simulation to the animation
+    pub fn capture(&mut self, frame: Vec<u8>, width: usize, height: usize) {
+        if self.writer.is_none() {
+            match AnimationWriter::create(&self.file_name, width, height, self.delay, self.scale) {
+                Ok(writer) => self.writer = Some(Arc::new(Mutex::new(writer))),
+                Err(err) => {
+                    eprintln!(
+                        "Error encountered writing animation to file: {:?}. Disabling it",
+                        err
+                    );
+                    self.every = None;
+                    return;
+                }
+            }
+        }
+        let result = self.writer.as_ref().unwrap().lock().unwrap().add(frame);
+        if let Err(err) = result {
+            eprintln!(
+                "Error encountered writing animation to file: {:?}. Disabling it",
+                err
+            );
+            self.writer = None;
+            self.every = None;
+        }
+    }
+
+    /// Start the animation over for a new run. The file is started again with the next frame
+    pub fn restart(&mut self) {
+        self.writer = None;
+    }
+
+    /// Write the animation for a finished simulation. Frames taken while it grew are used if there are any, otherwise
+    /// its ids are replayed
+    pub(crate) fn finish(self, sim: &mut Dla) {
+        let writer = match self.writer {
+            Some(writer) => writer,
+            None => {
+                replay(sim, &self.file_name, self.frames, self.delay, self.scale);
+                return;
+            }
+        };
+        let mut writer = writer.lock().unwrap();
+        // the last particles may not have made a full K
+        let last = sim.render();
+        let result = if writer.last.as_ref() != Some(&last) {
+            writer.add(last)
+        } else {
+            Ok(())
+        };
+        report(result.and_then(|()| writer.finish()), &self.file_name);
+    }
+}
+
+/// Draw `frames` frames of the simulation's grid growing, evenly spaced by id, ending with the whole grid, and write
+/// them as an animation
+pub(crate) fn replay(sim: &mut Dla, file_name: &str, frames: usize, delay: u16, scale: usize) {
+    let frames = frames.max(1);
+    let last_id = sim.last_id();
+    let timeline = sim.timeline();
+    let (width, height) = sim.size();
+    let result =
+        AnimationWriter::create(file_name, width, height, delay, scale).and_then(|mut writer| {
+            for frame in 1..=frames {
+                sim.handle_timeline_changed(Some(last_id * frame / frames));
+                writer.add(sim.render())?;
+            }
+            writer.finish()
+        });
+    sim.handle_timeline_changed(timeline);
+    report(result, file_name);
+}
+
+fn report(result: std::io::Result<()>, file_name: &str) {
+    if let Err(err) = result {
+        eprintln!(
+            "Error encountered writing animation to file {}: {:?}",
+            file_name, err
+        );
+    }
+}
+
+enum Encoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+/// An animation being written out a frame at a time
+pub struct AnimationWriter {
+    /// None once the animation is finished
+    encoder: Option<Encoder>,
+    file_name: String,
+    width: usize,
+    height: usize,
+    /// Milliseconds each frame is shown for
+    delay: u16,
+    scale: usize,
+    frames: u32,
+    /// The last frame written, so a finished simulation doesn't end on the same frame twice
+    last: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for AnimationWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AnimationWriter")
+            .field("file_name", &self.file_name)
+            .field("frames", &self.frames)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AnimationWriter {
+    /// Start an animation of `width` x `height` RGBA frames, printing where it's going like Grid::to_file does
+    pub fn create(
+        file_name: &str,
+        width: usize,
+        height: usize,
+        delay: u16,
+        scale: usize,
+    ) -> std::io::Result<Self> {
+        eprintln!("Writing animation out to file: {}", file_name);
+        let scale = scale.max(1);
+        let (scaled_width, scaled_height) = (width * scale, height * scale);
+        let file = BufWriter::new(File::create(file_name)?);
+        let encoder = if file_name.to_ascii_lowercase().ends_with(".gif") {
+            Encoder::Gif(gif_encoder(file, scaled_width, scaled_height)?)
+        } else {
+            Encoder::Apng(apng_encoder(file, scaled_width, scaled_height, delay)?)
+        };
+        Ok(Self {
+            encoder: Some(encoder),
+            file_name: file_name.to_string(),
+            width,
+            height,
+            delay,
+            scale,
+            frames: 0,
+            last: None,
+        })
+    }
+
+    /// Encode the next frame
+    pub fn add(&mut self, frame: Vec<u8>) -> std::io::Result<()> {
+        if frame.len() != self.width * self.height * 4 {
+            return Err(std::io::Error::other(
+                "every frame of an animation has to be the same size",
+            ));
+        }
+        let (width, height, scale) = (self.width, self.height, self.scale);
+        match self.encoder.as_mut() {
+            Some(Encoder::Gif(encoder)) => {
+                let (indices, palette) = index_colors(&frame)?;
+                let mut gif_frame = gif::Frame::from_palette_pixels(
+                    (width * scale) as u16,
+                    (height * scale) as u16,
+                    &upscale_indices(&indices, width, height, scale),
+                    &palette,
+                    None,
+                );
+                // GIF delays are in hundredths of a second
+                gif_frame.delay = self.delay / 10;
+                encoder
+                    .write_frame(&gif_frame)
+                    .map_err(std::io::Error::other)?;
+            }
+            Some(Encoder::Apng(writer)) => {
+                writer.write_image_data(&image::upscale(&frame, width, height, scale))?;
+            }
+            None => return Err(std::io::Error::other("the animation is already finished")),
+        }
+        self.frames += 1;
+        self.last = Some(frame);
+        Ok(())
+    }
+
+    /// Finish the file, once every frame is in
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self.encoder.take() {
+            Some(Encoder::Gif(encoder)) => encoder.into_inner()?.flush(),
+            Some(Encoder::Apng(writer)) => {
+                writer.finish()?;
+                set_apng_frames(&self.file_name, self.frames)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Start a looping GIF. Every frame has its own palette, since the colors to come aren't known yet
+fn gif_encoder(
+    file: BufWriter<File>,
+    width: usize,
+    height: usize,
+) -> std::io::Result<gif::Encoder<BufWriter<File>>> {
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(std::io::Error::other(
+            "GIFs can't be more than 65535 pixels wide",
+        ));
+    }
+    let mut encoder =
+        gif::Encoder::new(file, width as u16, height as u16, &[]).map_err(std::io::Error::other)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(std::io::Error::other)?;
+    Ok(encoder)
+}
+
+/// Palette indices of every pixel of an RGBA frame, and the palette. That's plenty for the themes and plain colors the
+/// simulation draws with
+fn index_colors(frame: &[u8]) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(frame.len() / 4);
+    for pixel in frame.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let next = palette.len();
+        if next == 256 && !palette.contains_key(&color) {
+            return Err(std::io::Error::other(
+                "more than 256 colors, try an animated PNG",
+            ));
+        }
+        indices.push(*palette.entry(color).or_insert(next as u8));
+    }
+    let mut colors = vec![0; palette.len() * 3];
+    for (color, index) in palette.iter() {
+        colors[*index as usize * 3..*index as usize * 3 + 3].copy_from_slice(color);
+    }
+    Ok((indices, colors))
+}
+
+/// Scale palette indices up by an integer factor, the same way image::upscale scales pixels
+fn upscale_indices(indices: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(indices.len() * scale * scale);
+    for row in indices.chunks_exact(width).take(height) {
+        let scaled_row: Vec<u8> = row
+            .iter()
+            .flat_map(|&i| std::iter::repeat_n(i, scale))
+            .collect();
+        for _ in 0..scale {
+            scaled.extend_from_slice(&scaled_row);
+        }
+    }
+    scaled
+}
+
+/// Start a looping animated PNG. Its frame count is a placeholder until set_apng_frames fills it in
+fn apng_encoder(
+    file: BufWriter<File>,
+    width: usize,
+    height: usize,
+    delay: u16,
+) -> std::io::Result<png::Writer<BufWriter<File>>> {
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // 0 plays loops forever
+    encoder.set_animated(u32::MAX, 0)?;
+    encoder.set_frame_delay(delay, 1000)?;
+    Ok(encoder.write_header()?)
+}
+
+/// Byte offset of the acTL chunk, which the png encoder writes right after the signature and IHDR
+const ACTL_OFFSET: u64 = 8 + 25;
+
+/// Set the frame count of an animated PNG written by apng_encoder, now that it's known
+fn set_apng_frames(file_name: &str, frames: u32) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(file_name)?;
+    // length, type, frame count, play count
+    let mut chunk = [0u8; 16];
+    file.seek(SeekFrom::Start(ACTL_OFFSET))?;
+    file.read_exact(&mut chunk)?;
+    if &chunk[4..8] != b"acTL" {
+        return Err(std::io::Error::other(
+            "the animation control chunk isn't where it should be",
+        ));
+    }
+    chunk[8..12].copy_from_slice(&frames.to_be_bytes());
+    // the crc covers the chunk type and data
+    let crc = crc32fast::hash(&chunk[4..]);
+    file.seek(SeekFrom::Start(ACTL_OFFSET + 8))?;
+    file.write_all(&chunk[8..])?;
+    file.write_all(&crc.to_be_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAMES: usize = 5;
+
+    /// Record FRAMES frames of a 4x3 image at scale 2, each with one more pixel lit
+    fn record(file_name: &str) {
+        let mut writer = AnimationWriter::create(file_name, 4, 3, 50, 2).unwrap();
+        let mut frame = vec![0; 4 * 3 * 4];
+        for i in 0..FRAMES {
+            frame[i * 4..i * 4 + 4].copy_from_slice(&[255, 128, i as u8, 255]);
+            writer.add(frame.clone()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn apng_frame_count() {
+        let file = std::env::temp_dir().join(format!("dla-anim-{}.png", std::process::id()));
+        let file = file.to_string_lossy();
+        record(&file);
+        let data = std::fs::read(&*file).unwrap();
+        std::fs::remove_file(&*file).unwrap();
+
+        // walk the chunks after the signature, checking every crc
+        let (mut offset, mut actl, mut fctl) = (8, None, 0);
+        while offset < data.len() {
+            let length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &data[offset + 4..offset + 8];
+            let body = &data[offset + 8..offset + 8 + length];
+            let crc = u32::from_be_bytes(
+                data[offset + 8 + length..offset + 12 + length]
+                    .try_into()
+                    .unwrap(),
+            );
+            assert_eq!(crc, crc32fast::hash(&data[offset + 4..offset + 8 + length]));
+            match kind {
+                b"acTL" => {
+                    assert_eq!(offset as u64, ACTL_OFFSET);
+                    actl = Some(u32::from_be_bytes(body[..4].try_into().unwrap()));
+                }
+                b"fcTL" => fctl += 1,
+                _ => {}
+            }
+            offset += 12 + length;
+        }
+        assert_eq!(actl, Some(FRAMES as u32));
+        assert_eq!(fctl, FRAMES);
+
+        // and a decoder agrees
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, FRAMES as u32);
+        assert_eq!((reader.info().width, reader.info().height), (8, 6));
+    }
+
+    #[test]
+    fn gif_frame_count() {
+        let file = std::env::temp_dir().join(format!("dla-anim-{}.gif", std::process::id()));
+        let file = file.to_string_lossy();
+        record(&file);
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&*file).unwrap()).unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (8, 6));
+            assert_eq!(frame.delay, 5);
+            frames += 1;
+        }
+        std::fs::remove_file(&*file).unwrap();
+        assert_eq!(frames, FRAMES);
+    }
+}
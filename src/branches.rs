@@ -0,0 +1,323 @@
+//! Branch structure of an aggregate, in the style of river network statistics
+//!
+//! The aggregate's skeleton is a graph where filled cells are nodes, connected to their filled 8-neighbors. It's
+//! reduced to a tree by a breadth first search from the seeds, so every particle flows into a neighbor that's as few
+//! links from a seed as possible. All seeds together form a single root. The skeleton only depends on which cells are
+//! filled, not on particle ids, so it's the same for grids saved without them.
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::grid::Grid;
+
+/// Statistics of the branches between junctions and tips
+#[derive(Serialize, Debug)]
+pub struct BranchStats {
+    /// Number of branches, one per tip. A branch is a chain of particles from where it splits off a bigger branch (or
+    /// the root) out to a tip, following the bigger subtree at every junction
+    pub count: usize,
+    pub mean_length: f64,
+    pub max_length: usize,
+    /// (length, number of branches with that length), shortest first
+    pub length_distribution: Vec<(usize, usize)>,
+}
+
+/// Statistics of the streams of one Strahler order
+#[derive(Serialize, Debug)]
+pub struct OrderStats {
+    pub order: usize,
+    /// Number of streams (maximal chains of particles with this order)
+    pub streams: usize,
+    /// Average number of particles per stream
+    pub mean_length: f64,
+    /// Streams of this order / streams of the next order. None for the highest order
+    pub bifurcation_ratio: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BranchReport {
+    pub particles: usize,
+    /// Particles nothing grew from
+    pub tips: usize,
+    /// Particles with 2 or more children
+    pub junctions: usize,
+    pub branches: BranchStats,
+    /// Highest Strahler order of any particle
+    pub max_order: usize,
+    pub orders: Vec<OrderStats>,
+    /// Average of the bifurcation ratios over all orders that have one
+    pub mean_bifurcation_ratio: Option<f64>,
+}
+
+/// The skeleton tree. Node 0 is the root (all seeds), the rest are the non-seed filled cells in breadth first order, so
+/// children always come after their parent
+struct Skeleton {
+    /// Parent node of each node. The root is its own parent
+    parents: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    /// Number of nodes in the subtree under each node, including itself
+    subtree_sizes: Vec<usize>,
+}
+
+impl Skeleton {
+    fn from_grid(grid: &Grid) -> Self {
+        let (width, height) = (grid.width as isize, grid.height as isize);
+        // node index of each cell, once it's been visited. Seeds are all node 0
+        let mut node_of_cell: Vec<Option<usize>> = vec![None; grid.cells.len()];
+        let mut parents = vec![0];
+        let mut queue = VecDeque::new();
+        for (idx, cell) in grid.cells.iter().enumerate() {
+            if cell.filled && cell.id == 0 {
+                node_of_cell[idx] = Some(0);
+                queue.push_back(idx);
+            }
+        }
+
+        // pieces that don't touch a seed (for example after detachment) start from their oldest particle
+        let mut by_age: Vec<(usize, usize)> = grid
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.filled && cell.id != 0)
+            .map(|(idx, cell)| (cell.id, idx))
+            .collect();
+        by_age.sort_unstable();
+        let mut by_age = by_age.into_iter();
+
+        loop {
+            while let Some(idx) = queue.pop_front() {
+                let node = node_of_cell[idx].unwrap();
+                let (x, y) = ((idx % grid.width) as isize, (idx / grid.width) as isize);
+                for (nx, ny) in [
+                    (x - 1, y - 1),
+                    (x, y - 1),
+                    (x + 1, y - 1),
+                    (x - 1, y),
+                    (x + 1, y),
+                    (x - 1, y + 1),
+                    (x, y + 1),
+                    (x + 1, y + 1),
+                ] {
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        continue;
+                    }
+                    let n_idx = (nx + ny * width) as usize;
+                    if grid.cells[n_idx].filled && node_of_cell[n_idx].is_none() {
+                        node_of_cell[n_idx] = Some(parents.len());
+                        parents.push(node);
+                        queue.push_back(n_idx);
+                    }
+                }
+            }
+
+            match by_age.find(|(_, idx)| node_of_cell[*idx].is_none()) {
+                Some((_, idx)) => {
+                    node_of_cell[idx] = Some(parents.len());
+                    parents.push(0);
+                    queue.push_back(idx);
+                }
+                None => break,
+            }
+        }
+
+        let mut children = vec![Vec::new(); parents.len()];
+        let mut subtree_sizes = vec![1; parents.len()];
+        for node in (1..parents.len()).rev() {
+            children[parents[node]].push(node);
+            subtree_sizes[parents[node]] += subtree_sizes[node];
+        }
+        // oldest child first, since they were pushed in reverse
+        for list in children.iter_mut() {
+            list.reverse();
+        }
+        Self {
+            parents,
+            children,
+            subtree_sizes,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.parents.len()
+    }
+}
+
+/// Strahler order of every node of the tree. Tips are order 1. A node whose children's highest order is k has order
+/// k + 1 if at least 2 children have order k, and order k otherwise
+fn strahler_orders(tree: &Skeleton) -> Vec<usize> {
+    let mut orders = vec![1; tree.len()];
+    // children always come after their parent, so go backwards
+    for node in (0..tree.len()).rev() {
+        let child_orders = tree.children[node].iter().map(|child| orders[*child]);
+        let highest = child_orders.clone().max();
+        if let Some(highest) = highest {
+            let at_highest = child_orders.filter(|order| *order == highest).count();
+            orders[node] = if at_highest >= 2 {
+                highest + 1
+            } else {
+                highest
+            };
+        }
+    }
+    orders
+}
+
+/// The child of `node` with the biggest subtree, the first one found on a tie
+fn main_child(tree: &Skeleton, node: usize) -> Option<usize> {
+    tree.children[node]
+        .iter()
+        .copied()
+        .max_by_key(|child| (tree.subtree_sizes[*child], std::cmp::Reverse(*child)))
+}
+
+/// Analyze the branch structure of the grid's aggregate
+pub fn branch_report(grid: &Grid) -> BranchReport {
+    let tree = Skeleton::from_grid(grid);
+    let nodes = tree.len();
+    let children = &tree.children;
+
+    let tips = (1..nodes).filter(|n| children[*n].is_empty()).count();
+    let junctions = (1..nodes).filter(|n| children[*n].len() >= 2).count();
+
+    // at every junction the branch carries on into the child with the biggest subtree (its main stem), and the other
+    // children start branches of their own. Each branch runs from where it starts down to a tip, so on a lattice the
+    // one-particle twigs along a stem don't cut it into pieces
+    let mut lengths: BTreeMap<usize, usize> = BTreeMap::new();
+    for start in 1..nodes {
+        let parent = tree.parents[start];
+        if parent != 0 && main_child(&tree, parent) == Some(start) {
+            continue;
+        }
+        let mut length = 1;
+        let mut node = start;
+        while let Some(child) = main_child(&tree, node) {
+            node = child;
+            length += 1;
+        }
+        *lengths.entry(length).or_insert(0) += 1;
+    }
+    let count: usize = lengths.values().sum();
+    let total_length: usize = lengths.iter().map(|(len, n)| len * n).sum();
+    let branches = BranchStats {
+        count,
+        mean_length: if count == 0 {
+            0.0
+        } else {
+            total_length as f64 / count as f64
+        },
+        max_length: lengths.keys().copied().max().unwrap_or(0),
+        length_distribution: lengths.into_iter().collect(),
+    };
+
+    // a stream of order k ends at each order k node whose parent has a higher order (or is the root)
+    let orders = strahler_orders(&tree);
+    // the root joins every branch from the seeds, so it can be an order above anything that actually grew
+    let max_order = orders[1..].iter().copied().max().unwrap_or(0);
+    let mut streams = vec![0_usize; max_order + 1];
+    let mut nodes_of_order = vec![0_usize; max_order + 1];
+    for node in 1..nodes {
+        let order = orders[node];
+        nodes_of_order[order] += 1;
+        let parent = tree.parents[node];
+        if parent == 0 || orders[parent] > order {
+            streams[order] += 1;
+        }
+    }
+    let order_stats: Vec<OrderStats> = (1..=max_order)
+        .map(|order| OrderStats {
+            order,
+            streams: streams[order],
+            mean_length: if streams[order] == 0 {
+                0.0
+            } else {
+                nodes_of_order[order] as f64 / streams[order] as f64
+            },
+            bifurcation_ratio: if order < max_order && streams[order + 1] > 0 {
+                Some(streams[order] as f64 / streams[order + 1] as f64)
+            } else {
+                None
+            },
+        })
+        .collect();
+    let ratios: Vec<f64> = order_stats
+        .iter()
+        .filter_map(|o| o.bifurcation_ratio)
+        .collect();
+    let mean_bifurcation_ratio = if ratios.is_empty() {
+        None
+    } else {
+        Some(ratios.iter().sum::<f64>() / ratios.len() as f64)
+    };
+
+    BranchReport {
+        particles: nodes - 1,
+        tips,
+        junctions,
+        branches,
+        max_order,
+        orders: order_stats,
+        mean_bifurcation_ratio,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+
+    /// A grid with a seed at `seed` and particles at `cells`, ids in order
+    fn grid(seed: (usize, usize), cells: &[(usize, usize)]) -> Grid {
+        let mut grid = Grid {
+            cells: vec![
+                Particle {
+                    filled: false,
+                    id: 0
+                };
+                12 * 12
+            ],
+            width: 12,
+            height: 12,
+        };
+        grid.cells[seed.0 + seed.1 * 12].filled = true;
+        for (i, (x, y)) in cells.iter().enumerate() {
+            grid.cells[x + y * 12] = Particle {
+                filled: true,
+                id: i + 1,
+            };
+        }
+        grid
+    }
+
+    #[test]
+    fn straight_line() {
+        let line: Vec<(usize, usize)> = (2..10).map(|x| (x, 5)).collect();
+        let report = branch_report(&grid((1, 5), &line));
+        assert_eq!(report.particles, 8);
+        assert_eq!(report.tips, 1);
+        assert_eq!(report.junctions, 0);
+        assert_eq!(report.branches.count, 1);
+        assert_eq!(report.branches.max_length, 8);
+        assert_eq!(report.max_order, 1);
+        assert_eq!(report.orders.len(), 1);
+        assert_eq!(report.orders[0].streams, 1);
+        assert_eq!(report.mean_bifurcation_ratio, None);
+    }
+
+    #[test]
+    fn y_shape() {
+        // a stem up from the seed that splits into 2 diagonal arms
+        let stem = [(5, 9), (5, 8), (5, 7)];
+        let arms = [(4, 6), (6, 6), (3, 5), (7, 5), (2, 4), (8, 4)];
+        let cells: Vec<(usize, usize)> = stem.iter().chain(arms.iter()).copied().collect();
+        let report = branch_report(&grid((5, 10), &cells));
+        assert_eq!(report.particles, 9);
+        assert_eq!(report.tips, 2);
+        assert_eq!(report.junctions, 1);
+        // the stem carries on into one arm
+        assert_eq!(report.branches.count, 2);
+        assert_eq!(report.branches.length_distribution, [(3, 1), (6, 1)]);
+        assert_eq!(report.max_order, 2);
+        assert_eq!(report.orders[0].streams, 2);
+        assert_eq!(report.orders[1].streams, 1);
+        assert_eq!(report.orders[0].bifurcation_ratio, Some(2.0));
+    }
+}
@@ -19,6 +19,11 @@ pub const COLORS: [Color; 11] = [
     RED, LIGHT_BLUE, PURPLE, PINK, CORAL, ORANGE, YELLOW, GREEN, SEAFOAM, WHITE, BLACK,
 ];
 
+/// Colors for telling seeds apart. Seeds past the end of the list reuse them from the start
+pub const SEED_COLORS: [Color; 8] = [
+    SEAFOAM, CORAL, LIGHT_BLUE, YELLOW, PURPLE, GREEN, ORANGE, PINK,
+];
+
 const SEAFOAM_GRADIENT: [Color; 10] = [
     [67, 193, 151, 0xff],
     [63, 174, 144, 0xff],
@@ -0,0 +1,804 @@
+//! Commands that run from the command line instead of opening the simulator window
+use colored::Colorize;
+
+use crate::analysis;
+use crate::animation;
+use crate::branches;
+use crate::components::{self, Ownership};
+use crate::config::{Command, DlaConfig};
+use crate::dla::Dla;
+use crate::ensemble;
+use crate::events;
+use crate::grid::Grid;
+use crate::harmonic;
+use crate::numpy;
+use crate::plot;
+use crate::stl::{self, Heights};
+use crate::svg;
+use crate::sweep;
+use crate::tree::AggregationTree;
+
+/// Run a command to completion. `config` holds the simulation settings given with the command
+pub fn run(command: &Command, config: &DlaConfig) {
+    match command {
+        Command::Ensemble {
+            runs,
+            threads,
+            out,
+            summary,
+        } => ensemble(config, *runs, *threads, out.as_deref(), summary.as_deref()),
+        Command::Sweep {
+            manifest,
+            out_dir,
+            threads,
+        } => sweep(manifest, out_dir, *threads),
+        Command::BoxCount {
+            file,
+            min_size,
+            max_size,
+        } => box_count(file, *min_size, *max_size),
+        Command::Gyration { file, out } => gyration(file, out.as_deref()),
+        Command::Correlation {
+            file,
+            out,
+            min_r,
+            max_r,
+        } => correlation(file, out.as_deref(), *min_r, *max_r),
+        Command::Tree { file, out } => tree(file, out.as_deref()),
+        Command::Branches { file, out } => branches(file, out.as_deref()),
+        Command::Lacunarity {
+            file,
+            min_size,
+            max_size,
+            out,
+        } => lacunarity(file, *min_size, *max_size, out.as_deref()),
+        Command::Angular {
+            file,
+            bins,
+            harmonics,
+            out,
+            harmonics_out,
+        } => angular(
+            file,
+            *bins,
+            *harmonics,
+            out.as_deref(),
+            harmonics_out.as_deref(),
+        ),
+        Command::Animate {
+            file,
+            out,
+            frames,
+            delay,
+            scale,
+        } => animate(file, out, *frames, *delay, *scale),
+        Command::Svg {
+            file,
+            out,
+            shape,
+            merge,
+            scale,
+            theme,
+        } => svg(config, file, out, shape, *merge, *scale, theme.as_deref()),
+        Command::Plot {
+            file,
+            out,
+            cell_size,
+            pen_width,
+        } => plot(file, out, *cell_size, *pen_width),
+        Command::Info { file } => info(file),
+        Command::Convert { file, out } => convert(file, out),
+        Command::Numpy { file, out, events } => numpy(file, out, events.as_deref()),
+        Command::Stl {
+            file,
+            out,
+            cell_size,
+            base,
+            column,
+            by_id,
+            min_column,
+        } => stl(
+            file,
+            out,
+            &Heights {
+                cell_size: *cell_size,
+                base: *base,
+                column: *column,
+                by_id: *by_id,
+                min_column: *min_column,
+            },
+        ),
+        Command::Components { file, out, labels } => {
+            components(file, out.as_deref(), labels.as_deref())
+        }
+        Command::Harmonic {
+            file,
+            probes,
+            spawn_radius,
+            min_size,
+            max_size,
+            min_q,
+            max_q,
+            q_step,
+            out,
+            measure,
+        } => harmonic(
+            file,
+            *probes,
+            *spawn_radius,
+            (*min_size, *max_size),
+            (*min_q, *max_q, *q_step),
+            out.as_deref(),
+            measure.as_deref(),
+        ),
+    }
+}
+
+fn ensemble(
+    config: &DlaConfig,
+    runs: usize,
+    threads: Option<usize>,
+    out: Option<&str>,
+    summary_out: Option<&str>,
+) {
+    let threads = threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    // print the seed so any run can be repeated
+    let base_seed = config.seed.unwrap_or_else(rand::random);
+    println!(
+        "{}{}{}{}{}{}",
+        "Runs: ".to_string().bold().blue(),
+        runs.to_string().green(),
+        "   Threads: ".to_string().bold().blue(),
+        threads.to_string().green(),
+        "   Base seed: ".to_string().bold().blue(),
+        base_seed.to_string().green()
+    );
+    if config.event_log.is_some()
+        || config.to_file.is_some()
+        || config.image.is_some()
+        || config.animation.is_some()
+        || config.stream.is_some()
+    {
+        eprintln!(
+            "Ignoring --event-log, --to-file, --image, --animation and --stream, every run would write to the same file"
+        );
+    }
+
+    let metrics = ensemble::run_ensemble(config, runs, threads, base_seed);
+    let summaries = ensemble::summarize(&metrics);
+    println!(
+        "{:>20} {:>6} {:>14} {:>14}",
+        "metric", "runs", "mean", "std dev"
+    );
+    for s in summaries.iter() {
+        println!(
+            "{:>20} {:>6} {:>14.4} {:>14.4}",
+            s.metric, s.runs, s.mean, s.std_dev
+        );
+    }
+
+    if let Some(out) = out {
+        match ensemble::to_csv(&metrics, out) {
+            Ok(()) => eprintln!("Wrote run metrics to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+    if let Some(summary_out) = summary_out {
+        match ensemble::summary_to_csv(&summaries, summary_out) {
+            Ok(()) => eprintln!("Wrote ensemble summary to file: {}", summary_out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", summary_out, err),
+        }
+    }
+}
+
+fn sweep(manifest: &str, out_dir: &str, threads: Option<usize>) {
+    let threads = threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    match sweep::run_sweep(manifest, out_dir, threads) {
+        Ok(()) => eprintln!("Wrote sweep to directory: {}", out_dir),
+        Err(err) => eprintln!("Error encountered running sweep {}: {}", manifest, err),
+    }
+}
+
+fn box_count(file: &str, min_size: usize, max_size: Option<usize>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return, // from_file already told the user what went wrong
+    };
+
+    let sizes = box_sizes(&grid, min_size, max_size);
+    let result = analysis::box_counting(&grid, &sizes);
+
+    println!("{:>10} {:>10}", "box size", "boxes");
+    for (size, count) in result.counts.iter() {
+        println!("{:>10} {:>10}", size, count);
+    }
+
+    match result.fit {
+        Some(fit) => println!(
+            "{}{}{}{}",
+            "Box-counting dimension: ".to_string().bold().blue(),
+            format!("{:.4}", fit.slope).green(),
+            "   R²: ".to_string().bold().blue(),
+            format!("{:.4}", fit.r_squared).green()
+        ),
+        None => eprintln!("Not enough box sizes to fit a dimension. Try a wider size range"),
+    }
+}
+
+/// Powers of 2 between min_size and max_size, or up to the default maximum if it isn't given
+fn box_sizes(grid: &Grid, min_size: usize, max_size: Option<usize>) -> Vec<usize> {
+    let sizes: Vec<usize> = match max_size {
+        Some(max_size) => std::iter::successors(Some(1_usize), |size| Some(size * 2))
+            .take_while(|size| *size <= max_size)
+            .collect(),
+        None => analysis::default_box_sizes(grid),
+    };
+    sizes.into_iter().filter(|size| *size >= min_size).collect()
+}
+
+fn gyration(file: &str, out: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let mass_radius = analysis::MassRadius::from_grid(&grid);
+    println!(
+        "{}{}",
+        "Particles: ".to_string().bold().blue(),
+        mass_radius.count().to_string().green()
+    );
+    println!(
+        "{}{}",
+        "Radius of gyration: ".to_string().bold().blue(),
+        format!("{:.3}", mass_radius.radius_of_gyration()).green()
+    );
+    println!(
+        "{}{}",
+        "Maximum radius: ".to_string().bold().blue(),
+        mass_radius.max_radius().to_string().green()
+    );
+    match mass_radius.fit() {
+        Some(fit) => println!(
+            "{}{}{}{}{}{}",
+            "Scaling exponent (Rg ~ N^v): ".to_string().bold().blue(),
+            format!("{:.4}", fit.slope).green(),
+            "   R²: ".to_string().bold().blue(),
+            format!("{:.4}", fit.r_squared).green(),
+            "   Dimension (1/v): ".to_string().bold().blue(),
+            format!("{:.4}", 1.0 / fit.slope).green()
+        ),
+        None => eprintln!("Not enough particles to fit a scaling exponent"),
+    }
+
+    if let Some(out) = out {
+        match mass_radius.to_csv(out) {
+            Ok(()) => eprintln!("Wrote radius of gyration series to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+}
+
+fn correlation(file: &str, out: Option<&str>, min_r: usize, max_r: Option<usize>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let correlation = analysis::correlation(&grid);
+    let max_r = max_r.unwrap_or(std::cmp::min(grid.width, grid.height) / 8);
+    match correlation.fit(min_r, max_r) {
+        Some(fit) => println!(
+            "{}{}{}{}{}{}",
+            "Correlation exponent (C(r) ~ r^-a): "
+                .to_string()
+                .bold()
+                .blue(),
+            format!("{:.4}", -fit.slope).green(),
+            "   R²: ".to_string().bold().blue(),
+            format!("{:.4}", fit.r_squared).green(),
+            "   Dimension (2 - a): ".to_string().bold().blue(),
+            format!("{:.4}", 2.0 + fit.slope).green()
+        ),
+        None => eprintln!(
+            "Not enough distances between {} and {} to fit an exponent",
+            min_r, max_r
+        ),
+    }
+
+    if let Some(out) = out {
+        match correlation.to_csv(out) {
+            Ok(()) => eprintln!("Wrote correlation function to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+}
+
+fn tree(file: &str, out: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    // a saved grid doesn't have the parents recorded during the simulation, so they're inferred from it
+    let tree = AggregationTree::from_grid(&grid, None);
+    let summary = [
+        ("Particles: ", tree.particles().to_string()),
+        ("Branch tips: ", tree.tips().len().to_string()),
+        (
+            "Branches from the seed: ",
+            tree.children[0].len().to_string(),
+        ),
+        ("Max depth: ", tree.max_depth().to_string()),
+        ("Mean depth: ", format!("{:.2}", tree.mean_depth())),
+    ];
+    for (label, value) in summary.iter() {
+        println!("{}{}", label.to_string().bold().blue(), value.green());
+    }
+
+    if let Some(out) = out {
+        match tree.to_csv(&grid, out) {
+            Ok(()) => eprintln!("Wrote aggregation tree to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+}
+
+fn branches(file: &str, out: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let report = branches::branch_report(&grid);
+    let json = serde_json::to_string_pretty(&report).expect("Branch report should serialize");
+    match out {
+        Some(out) => match std::fs::write(out, json) {
+            Ok(()) => eprintln!("Wrote branch report to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        },
+        None => println!("{}", json),
+    }
+}
+
+fn lacunarity(file: &str, min_size: usize, max_size: Option<usize>, out: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let result = analysis::lacunarity(&grid, &box_sizes(&grid, min_size, max_size));
+    println!("{:>10} {:>12}", "box size", "lacunarity");
+    for (size, lacunarity) in result.points.iter() {
+        println!("{:>10} {:>12.4}", size, lacunarity);
+    }
+
+    if let Some(out) = out {
+        match result.to_csv(out) {
+            Ok(()) => eprintln!("Wrote lacunarity to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+}
+
+fn angular(
+    file: &str,
+    bins: usize,
+    harmonics: usize,
+    out: Option<&str>,
+    harmonics_out: Option<&str>,
+) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    if bins == 0 {
+        eprintln!("Need at least 1 angular bin");
+        return;
+    }
+
+    let result = analysis::angular_distribution(&grid, bins, harmonics);
+    println!(
+        "{}{}{}{}",
+        "Particles: ".to_string().bold().blue(),
+        result.particles.to_string().green(),
+        "   Center: ".to_string().bold().blue(),
+        format!("({:.1}, {:.1})", result.center.0, result.center.1).green()
+    );
+    println!("{:>4} {:>10} {:>10}", "n", "amplitude", "phase");
+    for h in result.harmonics.iter() {
+        println!("{:>4} {:>10.4} {:>10.4}", h.n, h.amplitude, h.phase);
+    }
+    if let Some(h) = result.dominant_symmetry() {
+        println!(
+            "{}{}{}{}",
+            "Dominant symmetry: ".to_string().bold().blue(),
+            format!("{}-fold", h.n).green(),
+            "   Amplitude: ".to_string().bold().blue(),
+            format!("{:.4}", h.amplitude).green()
+        );
+    }
+
+    if let Some(out) = out {
+        match result.to_csv(out) {
+            Ok(()) => eprintln!("Wrote angular mass distribution to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+    if let Some(harmonics_out) = harmonics_out {
+        match result.harmonics_to_csv(harmonics_out) {
+            Ok(()) => eprintln!("Wrote angular harmonics to file: {}", harmonics_out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", harmonics_out, err),
+        }
+    }
+}
+
+fn animate(file: &str, out: &str, frames: usize, delay: u16, scale: usize) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    let mut sim = Dla::from_grid(grid);
+    animation::replay(&mut sim, out, frames, delay, scale);
+}
+
+fn svg(
+    config: &DlaConfig,
+    file: &str,
+    out: &str,
+    shape: &str,
+    merge: bool,
+    scale: f64,
+    theme: Option<&str>,
+) {
+    let shape = match svg::Shape::from(shape) {
+        Some(shape) => shape,
+        None => {
+            eprintln!("Unknown shape {}, options are circle and square", shape);
+            return;
+        }
+    };
+    let theme = match theme.map(DlaConfig::parse_theme) {
+        Some(None) => {
+            eprintln!("Unknown theme {}", theme.unwrap_or_default());
+            return;
+        }
+        other => other.flatten(),
+    };
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    // same colors as the simulation would use with these flags
+    let mut sim = Dla::from_grid(grid);
+    if let Some(color) = config.color() {
+        sim.handle_particle_color_changed(color);
+        sim.handle_theme_changed(None);
+    }
+    if let Some(background) = config.background_color() {
+        sim.handle_background_color_changed(background);
+    }
+    if let Some(theme) = theme {
+        sim.handle_theme_changed(theme);
+    }
+    let rgba = sim.render();
+    let background = sim.background_color().get_color();
+    match svg::write_svg(out, sim.grid(), &rgba, background, shape, merge, scale) {
+        Ok(()) => eprintln!("Wrote SVG to file: {}", out),
+        Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+    }
+}
+
+fn plot(file: &str, out: &str, cell_size: f64, pen_width: f64) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    let strokes = plot::strokes(&grid);
+    println!(
+        "{}{}{}{}",
+        "Strokes: ".to_string().bold().blue(),
+        strokes.strokes.len().to_string().green(),
+        "   Length (mm): ".to_string().bold().blue(),
+        format!("{:.1}", strokes.length() * cell_size).green()
+    );
+
+    let lower = out.to_ascii_lowercase();
+    let result = if lower.ends_with(".hpgl") || lower.ends_with(".plt") {
+        strokes.to_hpgl(out, cell_size)
+    } else {
+        strokes.to_svg(out, cell_size, pen_width)
+    };
+    match result {
+        Ok(()) => eprintln!("Wrote strokes to file: {}", out),
+        Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+    }
+}
+
+fn info(file: &str) {
+    let file = match Grid::read_file(file) {
+        Some(file) => file,
+        None => return,
+    };
+    let grid = &file.grid;
+    let p = &file.parameters;
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+    let summary = [
+        (
+            "Format version: ",
+            file.version
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "none (legacy grid file or another format)".to_string()),
+        ),
+        ("Size: ", format!("{}x{}", grid.width, grid.height)),
+        (
+            "Particles: ",
+            grid.cells.iter().filter(|c| c.filled).count().to_string(),
+        ),
+        ("Written by: ", optional(p.software.clone())),
+        ("Grid type: ", optional(p.grid_type.clone())),
+        (
+            "Target particles: ",
+            optional(p.particles.map(|n| n.to_string())),
+        ),
+        (
+            "Spawn radius: ",
+            optional(p.spawn_radius.map(|r| r.to_string())),
+        ),
+        ("Seed: ", optional(p.seed.map(|s| s.to_string()))),
+        ("Color: ", optional(p.color.clone())),
+        ("Background color: ", optional(p.background_color.clone())),
+        ("Theme: ", optional(p.theme.clone())),
+        ("Flags: ", optional(p.config.clone())),
+    ];
+    for (label, value) in summary.iter() {
+        println!("{}{}", label.to_string().bold().blue(), value.green());
+    }
+}
+
+fn convert(file: &str, out: &str) {
+    // the settings carry over, for formats that keep them
+    if let Some(file) = Grid::read_file(file) {
+        file.grid.to_file(out, &file.parameters);
+    }
+}
+
+fn numpy(file: &str, out: &str, events_file: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    let events = match events_file.map(events::read) {
+        Some(Ok(events)) => Some(events),
+        Some(Err(err)) => {
+            eprintln!(
+                "Error encountered reading {}: {:?}",
+                events_file.unwrap(),
+                err
+            );
+            return;
+        }
+        None => None,
+    };
+
+    let mask_file = format!("{}_mask.npy", out);
+    let ids_file = format!("{}_ids.npy", out);
+    let particles_file = format!("{}_particles.csv", out);
+    let results = [
+        (&mask_file, numpy::write_mask(&mask_file, &grid)),
+        (&ids_file, numpy::write_ids(&ids_file, &grid)),
+        (
+            &particles_file,
+            numpy::write_particles(&particles_file, &grid, events.as_deref()),
+        ),
+    ];
+    for (file, result) in results.iter() {
+        match result {
+            Ok(()) => eprintln!("Wrote {}", file),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", file, err),
+        }
+    }
+}
+
+fn stl(file: &str, out: &str, heights: &Heights) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    let mesh = stl::mesh(&grid, heights);
+    println!(
+        "{}{}",
+        "Triangles: ".to_string().bold().blue(),
+        mesh.triangles.len().to_string().green()
+    );
+    match mesh.to_stl(out) {
+        Ok(()) => eprintln!("Wrote mesh to file: {}", out),
+        Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+    }
+}
+
+fn components(file: &str, out: Option<&str>, labels_out: Option<&str>) {
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+
+    let components = components::label(&grid);
+    let mut sizes = components.sizes.clone();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    println!(
+        "{}{}{}{}",
+        "Connected components: ".to_string().bold().blue(),
+        sizes.len().to_string().green(),
+        "   Largest: ".to_string().bold().blue(),
+        sizes.first().copied().unwrap_or(0).to_string().green()
+    );
+
+    // the saved grid doesn't have the ownership recorded during the simulation, so it's replayed in stick order
+    let ownership = Ownership::from_grid(&grid);
+    println!(
+        "{:>6} {:>16} {:>10} {:>10} {:>12}",
+        "seed", "position", "mass", "cluster", "merged at"
+    );
+    for seed in 0..ownership.seeds() {
+        let (x, y) = ownership.seed_position(seed);
+        println!(
+            "{:>6} {:>16} {:>10} {:>10} {:>12}",
+            seed,
+            format!("({:.1}, {:.1})", x, y),
+            ownership.mass(seed),
+            ownership.cluster_of(seed),
+            ownership
+                .merged_at(seed)
+                .map(|m| m.id.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        );
+    }
+    for merge in ownership.merges() {
+        println!(
+            "Clusters {} and {} merged at particle {} (masses {} and {})",
+            merge.clusters.0, merge.clusters.1, merge.id, merge.masses.0, merge.masses.1
+        );
+    }
+
+    if let Some(out) = out {
+        match ownership.to_csv(out) {
+            Ok(()) => eprintln!("Wrote seed ownership to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+    if let Some(labels_out) = labels_out {
+        match write_labels(&grid, &components, &ownership, labels_out) {
+            Ok(()) => eprintln!("Wrote particle labels to file: {}", labels_out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", labels_out, err),
+        }
+    }
+}
+
+fn write_labels(
+    grid: &Grid,
+    components: &components::Components,
+    ownership: &Ownership,
+    file_name: &str,
+) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    writeln!(writer, "x,y,id,component,seed")?;
+    for (idx, cell) in grid.cells.iter().enumerate().filter(|(_, c)| c.filled) {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            idx % grid.width,
+            idx / grid.width,
+            cell.id,
+            components.labels[idx].unwrap(),
+            ownership
+                .owner(idx)
+                .map(|seed| seed.to_string())
+                .unwrap_or_default()
+        )?;
+    }
+    writer.flush()
+}
+
+fn harmonic(
+    file: &str,
+    probes: usize,
+    spawn_radius: Option<usize>,
+    (min_size, max_size): (usize, Option<usize>),
+    (min_q, max_q, q_step): (f64, f64, f64),
+    out: Option<&str>,
+    measure_out: Option<&str>,
+) {
+    if ![min_q, max_q, q_step].iter().all(|q| q.is_finite()) {
+        eprintln!("The q range and step have to be finite numbers");
+        return;
+    }
+    if q_step <= 0.0 {
+        eprintln!("The q step has to be positive");
+        return;
+    }
+    let grid = match Grid::from_file(file) {
+        Some(grid) => grid,
+        None => return,
+    };
+    if !grid.cells.iter().any(|cell| cell.filled) {
+        eprintln!("The grid is empty, there's no aggregate to probe");
+        return;
+    }
+
+    eprintln!("Launching {} probes...", probes);
+    let measure = harmonic::harmonic_measure(&grid, probes, spawn_radius);
+    println!(
+        "{}{}{}{}",
+        "Probes stuck: ".to_string().bold().blue(),
+        measure.probes.to_string().green(),
+        "   Growth sites: ".to_string().bold().blue(),
+        measure.growth_sites().to_string().green()
+    );
+
+    if let Some(measure_out) = measure_out {
+        match measure.to_csv(measure_out) {
+            Ok(()) => eprintln!("Wrote harmonic measure to file: {}", measure_out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", measure_out, err),
+        }
+    }
+
+    let steps = ((max_q - min_q) / q_step).round().max(0.0) as usize;
+    let mut qs: Vec<f64> = (0..=steps).map(|i| min_q + i as f64 * q_step).collect();
+    // always include the named dimensions so they can be reported
+    for q in [0.0, 1.0, 2.0, 3.0] {
+        if !qs.iter().any(|&other| (other - q).abs() < 1e-9) {
+            qs.push(q);
+        }
+    }
+    qs.sort_by(f64::total_cmp);
+
+    let sizes = box_sizes(&grid, min_size, max_size);
+    let spectrum = match measure.spectrum(&qs, &sizes) {
+        Some(spectrum) => spectrum,
+        None => {
+            eprintln!("Not enough probes or box sizes to compute a spectrum");
+            return;
+        }
+    };
+
+    for (q, name) in [
+        (0.0, "D0 (growth sites): "),
+        (1.0, "D1 (information): "),
+        (2.0, "D2 (correlation): "),
+        (3.0, "D3 (Turkevich-Scher, D/2): "),
+    ] {
+        if let Some(point) = spectrum.at(q) {
+            println!(
+                "{}{}",
+                name.to_string().bold().blue(),
+                format!("{:.4}", point.dimension).green()
+            );
+        }
+    }
+    let alphas = spectrum.points.iter().map(|p| p.alpha);
+    println!(
+        "{}{}{}{}",
+        "Alpha min: ".to_string().bold().blue(),
+        format!("{:.4}", alphas.clone().fold(f64::INFINITY, f64::min)).green(),
+        "   Alpha max: ".to_string().bold().blue(),
+        format!("{:.4}", alphas.fold(f64::NEG_INFINITY, f64::max)).green()
+    );
+
+    if let Some(out) = out {
+        match spectrum.to_csv(out) {
+            Ok(()) => eprintln!("Wrote multifractal spectrum to file: {}", out),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", out, err),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Connected components of an aggregate, and which seed each particle grew from
+//!
+//! Grids like FourDots and RandFive start with several seeds that grow separately until their clusters touch. A seed
+//! is a connected group of seed cells (id 0), so an edge or a circle is one seed. Every particle is owned by the seed
+//! its parent (see tree::parent_of) is owned by, so ownership follows the aggregation tree back to a seed. When a
+//! particle sticks touching particles owned by seeds in different clusters, those clusters merge.
+use std::collections::VecDeque;
+
+use crate::grid::Grid;
+use crate::tree;
+
+/// 8-connected components of a grid's filled cells
+#[derive(Clone, Debug)]
+pub struct Components {
+    /// Component of each cell, numbered in row-major order of their first cell. None for empty cells
+    pub labels: Vec<Option<usize>>,
+    /// Number of cells in each component
+    pub sizes: Vec<usize>,
+}
+
+/// Label the 8-connected components of the filled cells of `grid`
+pub fn label(grid: &Grid) -> Components {
+    label_cells(grid, |idx| grid.filled(idx))
+}
+
+/// Label the 8-connected components of the cells `include` returns true for
+fn label_cells(grid: &Grid, include: impl Fn(usize) -> bool) -> Components {
+    let (width, height) = (grid.width as isize, grid.height as isize);
+    let mut labels: Vec<Option<usize>> = vec![None; grid.cells.len()];
+    let mut sizes = Vec::new();
+    let mut queue = VecDeque::new();
+    for start in 0..grid.cells.len() {
+        if labels[start].is_some() || !include(start) {
+            continue;
+        }
+        let component = sizes.len();
+        let mut size = 0;
+        labels[start] = Some(component);
+        queue.push_back(start);
+        while let Some(idx) = queue.pop_front() {
+            size += 1;
+            let (x, y) = ((idx % grid.width) as isize, (idx / grid.width) as isize);
+            for ny in y - 1..=y + 1 {
+                for nx in x - 1..=x + 1 {
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        continue;
+                    }
+                    let n_idx = (nx + ny * width) as usize;
+                    if labels[n_idx].is_none() && include(n_idx) {
+                        labels[n_idx] = Some(component);
+                        queue.push_back(n_idx);
+                    }
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    Components { labels, sizes }
+}
+
+/// Two clusters joining into one
+#[derive(Clone, Copy, Debug)]
+pub struct MergeEvent {
+    /// Id of the particle that joined them. Ids increase in the order particles stick, so this is when they merged
+    pub id: usize,
+    /// The clusters that merged, each named by the lowest seed in it. The merged cluster keeps the lower name
+    pub clusters: (usize, usize),
+    /// Mass of each cluster just before they merged
+    pub masses: (usize, usize),
+}
+
+/// Which seed owns each particle, and how the seeds' clusters merged, tracked as particles stick
+#[derive(Clone, Debug, Default)]
+pub struct Ownership {
+    /// Seed that owns each cell. None for empty cells, and for particles that don't touch anything older
+    owners: Vec<Option<usize>>,
+    /// Centroid (x, y) of each seed
+    seed_positions: Vec<(f64, f64)>,
+    /// Number of cells each seed owns, including its own seed cells
+    masses: Vec<usize>,
+    /// Union-find parents over seeds. A seed that is its own parent names its cluster. Parents are always lower seeds
+    clusters: Vec<usize>,
+    merges: Vec<MergeEvent>,
+}
+
+impl Ownership {
+    /// Ownership of a grid that hasn't grown yet: every seed owns itself and nothing else
+    pub fn from_seeds(grid: &Grid) -> Self {
+        let seeds = label_cells(grid, |idx| grid.filled(idx) && grid.cells[idx].id == 0);
+        let mut sums = vec![(0.0, 0.0); seeds.sizes.len()];
+        for (idx, label) in seeds.labels.iter().enumerate() {
+            if let Some(seed) = label {
+                sums[*seed].0 += (idx % grid.width) as f64;
+                sums[*seed].1 += (idx / grid.width) as f64;
+            }
+        }
+        Self {
+            seed_positions: sums
+                .iter()
+                .zip(seeds.sizes.iter())
+                .map(|((x, y), size)| (x / *size as f64, y / *size as f64))
+                .collect(),
+            clusters: (0..seeds.sizes.len()).collect(),
+            masses: seeds.sizes,
+            owners: seeds.labels,
+            merges: Vec::new(),
+        }
+    }
+
+    /// Rebuild the ownership of a finished grid by adding its particles in the order they stuck
+    pub fn from_grid(grid: &Grid) -> Self {
+        let mut ownership = Self::from_seeds(grid);
+        let mut particles: Vec<(usize, usize)> = grid
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.filled && cell.id != 0)
+            .map(|(idx, cell)| (cell.id, idx))
+            .collect();
+        particles.sort_unstable();
+        for (_, idx) in particles {
+            ownership.add(grid, idx);
+        }
+        ownership
+    }
+
+    /// The cluster `seed` is part of, named by its lowest seed
+    pub fn cluster_of(&self, seed: usize) -> usize {
+        let mut seed = seed;
+        while self.clusters[seed] != seed {
+            seed = self.clusters[seed];
+        }
+        seed
+    }
+
+    /// Total mass of all the seeds in a cluster
+    fn cluster_mass(&self, cluster: usize) -> usize {
+        (0..self.seeds())
+            .filter(|&seed| self.cluster_of(seed) == cluster)
+            .map(|seed| self.masses[seed])
+            .sum()
+    }
+
+    /// Record the particle at `idx` sticking. It's owned by its parent's seed, and merges every cluster it touches.
+    /// Returns the merges it caused.
+    pub fn add(&mut self, grid: &Grid, idx: usize) -> Vec<MergeEvent> {
+        let owner = tree::parent_of(grid, idx).and_then(|parent| self.owners[parent]);
+        self.owners[idx] = owner;
+        let owner = match owner {
+            Some(owner) => owner,
+            None => return Vec::new(),
+        };
+        self.masses[owner] += 1;
+
+        let mut merges = Vec::new();
+        let (x, y) = ((idx % grid.width) as isize, (idx / grid.width) as isize);
+        for ny in y - 1..=y + 1 {
+            for nx in x - 1..=x + 1 {
+                if nx < 0 || ny < 0 || nx >= grid.width as isize || ny >= grid.height as isize {
+                    continue;
+                }
+                let neighbor = match self.owners[nx as usize + ny as usize * grid.width] {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                let (a, b) = (self.cluster_of(owner), self.cluster_of(neighbor));
+                if a == b {
+                    continue;
+                }
+                let (low, high) = (a.min(b), a.max(b));
+                let event = MergeEvent {
+                    id: grid.cells[idx].id,
+                    clusters: (low, high),
+                    masses: (self.cluster_mass(low), self.cluster_mass(high)),
+                };
+                self.clusters[high] = low;
+                self.merges.push(event);
+                merges.push(event);
+            }
+        }
+        merges
+    }
+
+    /// Record the particle at `idx` leaving. Merges it caused aren't undone
+    pub fn remove(&mut self, idx: usize) {
+        if let Some(owner) = self.owners[idx].take() {
+            self.masses[owner] -= 1;
+        }
+    }
+
+    pub fn seeds(&self) -> usize {
+        self.masses.len()
+    }
+
+    pub fn owner(&self, idx: usize) -> Option<usize> {
+        self.owners.get(idx).copied().flatten()
+    }
+
+    pub fn seed_position(&self, seed: usize) -> (f64, f64) {
+        self.seed_positions[seed]
+    }
+
+    /// Number of cells owned by a seed, including its own seed cells
+    pub fn mass(&self, seed: usize) -> usize {
+        self.masses[seed]
+    }
+
+    pub fn merges(&self) -> &[MergeEvent] {
+        &self.merges
+    }
+
+    /// The merge that joined `seed`'s cluster into a lower one, if it happened
+    pub fn merged_at(&self, seed: usize) -> Option<&MergeEvent> {
+        self.merges.iter().find(|m| m.clusters.1 == seed)
+    }
+
+    /// Write one row per seed: position, mass, the cluster it's part of and the id of the particle that merged it
+    /// into that cluster (empty if it hasn't merged)
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "seed,x,y,mass,cluster,merged_at")?;
+        for seed in 0..self.seeds() {
+            let (x, y) = self.seed_position(seed);
+            writeln!(
+                writer,
+                "{},{:.1},{:.1},{},{},{}",
+                seed,
+                x,
+                y,
+                self.mass(seed),
+                self.cluster_of(seed),
+                self.merged_at(seed)
+                    .map(|m| m.id.to_string())
+                    .unwrap_or_default()
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+
+    #[test]
+    fn seeds_merge_once() {
+        // two seeds on a 7x3 grid, grown towards each other. Particle 3 joins them, then 4 touches both again
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            21
+        ];
+        for (x, y, id) in [
+            (1, 1, 0),
+            (5, 1, 0),
+            (2, 1, 1),
+            (4, 1, 2),
+            (3, 1, 3),
+            (3, 2, 4),
+        ] {
+            cells[x + y * 7] = Particle { filled: true, id };
+        }
+        let grid = Grid {
+            cells,
+            width: 7,
+            height: 3,
+        };
+
+        let mut ownership = Ownership::from_seeds(&grid);
+        assert_eq!(ownership.seeds(), 2);
+        assert!(ownership.add(&grid, 2 + 7).is_empty());
+        assert!(ownership.add(&grid, 4 + 7).is_empty());
+        let merges = ownership.add(&grid, 3 + 7);
+        assert!(ownership.add(&grid, 3 + 14).is_empty());
+
+        assert_eq!(merges.len(), 1);
+        assert_eq!(merges[0].id, 3);
+        assert_eq!(merges[0].clusters, (0, 1));
+        // particle 3 belongs to seed 0, which has its seed, 1 and 3
+        assert_eq!(merges[0].masses, (3, 2));
+        assert_eq!(ownership.cluster_of(1), 0);
+        assert_eq!(ownership.merged_at(1).map(|merge| merge.id), Some(3));
+
+        // rebuilding from the finished grid sees the same merge
+        let rebuilt = Ownership::from_grid(&grid);
+        assert_eq!(rebuilt.merges().len(), 1);
+        assert_eq!(rebuilt.merges()[0].masses, (3, 2));
+        assert_eq!(rebuilt.mass(0), 4);
+        assert_eq!(rebuilt.mass(1), 2);
+    }
+}
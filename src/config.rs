@@ -1,6 +1,9 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
+use crate::colors::{ColorName, Theme};
+
 pub const ABOUT: &str = r"
 _____  __      __      ____  __  __  ___  ____ 
 (  _ \(  )    /__\    (  _ \(  )(  )/ __)(_  _)
@@ -27,6 +30,8 @@ pub struct DlaConfig {
     /// BottomEdge: the bottom edge is filled.  
     /// AllEdges: all edges are filled
     /// FourDots: a single particle in each of the 4 quadrants of the grid
+    /// RandFive: 5 particles in random places
+    /// Circle: a ring of particles around the center
     #[arg(short, long)]
     pub grid_type: Option<String>,
 
@@ -40,12 +45,101 @@ pub struct DlaConfig {
     #[arg(long)]
     pub background_color: Option<String>,
 
+    /// Noise reduction: the number of times walkers have to touch a perimeter site before it fills.
+    /// Walkers that touch a site before that are removed. Leave unset for classic DLA
+    #[arg(long)]
+    pub noise_reduction: Option<u32>,
+
+    /// Surface relaxation temperature. When set, stuck particles with few neighbors can hop along the
+    /// surface or detach. Higher temperatures move particles more often. Leave unset to disable relaxation
+    #[arg(long)]
+    pub temperature: Option<f64>,
+
+    /// Remove walkers that haven't stuck after walking this many steps. A new walker spawns in their place
+    #[arg(long)]
+    pub max_walk: Option<u64>,
+
+    /// Probability that a walker evaporates (is removed) on each step. A new walker spawns in its place
+    #[arg(long)]
+    pub evaporation: Option<f64>,
+
+    /// Probability that a walker sticks each time it touches the aggregate. Otherwise it keeps walking.
+    /// Leave unset for classic DLA, where walkers always stick
+    #[arg(long)]
+    pub stickiness: Option<f64>,
+
+    /// Only spawn walkers further than this from the center of the grid
+    #[arg(long)]
+    pub spawn_radius: Option<usize>,
+
+    /// Seed for the random number generator. Runs with the same seed and settings grow the same aggregate.
+    /// Leave unset for a different aggregate every run
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Log every particle that sticks to this file. Files ending in .csv are written as CSV,
+    /// anything else uses a compact binary format. Relaxation moves (see --temperature) aren't logged, so with relaxation
+    /// on the log won't replay to the final grid
+    #[arg(long)]
+    pub event_log: Option<String>,
+
     /// After simulation, write the grid to this file
     #[arg(short, long)]
     pub to_file: Option<String>,
 
+    /// After simulation, draw the grid to this PNG file with the current colors or theme
+    #[arg(long)]
+    pub image: Option<String>,
+
+    /// Scale the image written with --image and the animation written with --animation up by this whole number, so
+    /// each particle is a square this many pixels wide
+    #[arg(long, default_value_t = 1)]
+    pub image_scale: usize,
+
+    /// After simulation, write an animation of the aggregate growing to this file.
+    /// Files ending in .gif are GIFs, anything else is an animated PNG
+    #[arg(long)]
+    pub animation: Option<String>,
+
+    /// Number of frames in the animation, replayed from the order particles stuck in
+    #[arg(long, default_value_t = 100)]
+    pub frames: usize,
+
+    /// Take an animation frame every this many stuck particles while the simulation runs, instead of replaying it
+    /// at the end. Shows particles that moved or detached where they were at the time
+    #[arg(long)]
+    pub frame_every: Option<usize>,
+
+    /// Milliseconds each animation frame is shown for. GIFs round this down to hundredths of a second
+    #[arg(long, default_value_t = 40)]
+    pub frame_delay: u16,
+
+    /// Write frames to this file as the simulation runs, for piping into a video encoder. Use - for standard output
+    #[arg(long)]
+    pub stream: Option<String>,
+
+    /// Format of the frame stream. Options are:
+    /// Raw: RGBA frames back to back with no header
+    /// Y4M: a YUV4MPEG2 stream that most video encoders read directly.
+    /// Defaults to Y4M for files ending in .y4m and raw otherwise
+    #[arg(long)]
+    pub stream_format: Option<String>,
+
+    /// Stream a frame every this many stuck particles
+    #[arg(long, default_value_t = 10)]
+    pub stream_every: usize,
+
+    /// Stream a frame every this many updates instead of every --stream-every stuck particles
+    #[arg(long)]
+    pub stream_every_updates: Option<u64>,
+
+    /// Frame rate written in Y4M streams
+    #[arg(long, default_value_t = 30)]
+    pub stream_fps: u32,
+
     /// Read a grid in from this file and display it.
-    /// Grid size is inferred from the file you read in, so no need to specify it
+    /// Grid size is inferred from the file you read in, so no need to specify it. With --view skip it isn't displayed,
+    /// only written to --to-file, --image and --animation
     #[arg(short, long)]
     pub from_file: Option<String>,
 
@@ -56,7 +150,7 @@ pub struct DlaConfig {
     pub color_style: Option<String>,
 
     /// When the grid is displayed, each particle's size will be shown as a factor of the pixel size
-    #[arg(long, default_value_t = 1.0)]
+    #[arg(long, default_value_t = 1.25)]
     pub scale_factor: f64,
 
     /// Run a benchmark of the simulation to get an approximation of updates/sec. An update is a single move/spawn of a particle.
@@ -70,6 +164,336 @@ pub struct DlaConfig {
     /// Skip: Don't show the display at all
     #[arg(long, short)]
     pub view: Option<String>,
+
+    /// Run a command instead of the simulator
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands that run without opening a window
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Grow many aggregates with the same settings and different seeds, in parallel, and report statistics over them.
+    /// Settings come from the flags before the command, for example: dla -p 5000 -g fourdots ensemble --runs 20
+    Ensemble {
+        /// Number of aggregates to grow
+        #[arg(short, long, default_value_t = 10)]
+        runs: usize,
+
+        /// Number of runs to do at the same time. Defaults to the number of cores
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Write every run's metrics to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Write the mean and standard deviation of every metric to this CSV file
+        #[arg(long)]
+        summary: Option<String>,
+    },
+
+    /// Grow an aggregate for every combination of parameters in a manifest file. See the sweep module for the format
+    Sweep {
+        /// TOML manifest of the parameter values to sweep over. JSON if the file name ends in .json
+        manifest: String,
+
+        /// Directory to write grids, images and the index to. Rerunning into the same directory resumes the sweep
+        #[arg(short, long, default_value = "sweep")]
+        out_dir: String,
+
+        /// Number of runs to do at the same time. Defaults to the number of cores
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+
+    /// Compute the box-counting fractal dimension of a saved grid
+    BoxCount {
+        /// The grid file to analyze
+        file: String,
+
+        /// The smallest box edge length to count with
+        #[arg(long, default_value_t = 1)]
+        min_size: usize,
+
+        /// The largest box edge length to count with. Defaults to a quarter of the shortest grid edge
+        #[arg(long)]
+        max_size: Option<usize>,
+    },
+
+    /// Compute the radius of gyration of a saved grid and fit its mass–radius scaling exponent.
+    /// Particles are added back in the order they stuck to rebuild the growth history
+    Gyration {
+        /// The grid file to analyze
+        file: String,
+
+        /// Write the number of particles vs radius of gyration series to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Compute the radially averaged density–density correlation function C(r) of a saved grid with an FFT,
+    /// and fit its exponent
+    Correlation {
+        /// The grid file to analyze
+        file: String,
+
+        /// Write C(r) to this CSV file, along with C(r) measured along the axes and the diagonals
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// The smallest distance to include in the fit
+        #[arg(long, default_value_t = 2)]
+        min_r: usize,
+
+        /// The largest distance to include in the fit. Defaults to an eighth of the shortest grid edge
+        #[arg(long)]
+        max_r: Option<usize>,
+    },
+
+    /// Extract the aggregation tree of a saved grid (which particle each particle stuck to) and summarize it
+    Tree {
+        /// The grid file to analyze
+        file: String,
+
+        /// Write each particle's parent, depth (chemical distance to the seed) and subtree size to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Report the branch structure of a saved grid as JSON: branch counts and lengths, tips, Strahler orders and
+    /// bifurcation ratios. Branches follow the skeleton of the 8-connected cells, grown out from the seeds
+    Branches {
+        /// The grid file to analyze
+        file: String,
+
+        /// Write the report to this file instead of printing it
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Compute the gliding-box lacunarity of a saved grid at a range of box sizes
+    Lacunarity {
+        /// The grid file to analyze
+        file: String,
+
+        /// The smallest box edge length
+        #[arg(long, default_value_t = 1)]
+        min_size: usize,
+
+        /// The largest box edge length. Defaults to a quarter of the shortest grid edge
+        #[arg(long)]
+        max_size: Option<usize>,
+
+        /// Write the lacunarity at each box size to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
+    /// Measure how a saved grid's mass is spread around its seeds, and the Fourier harmonics of that distribution
+    Angular {
+        /// The grid file to analyze
+        file: String,
+
+        /// Number of equal angular bins
+        #[arg(long, default_value_t = 72)]
+        bins: usize,
+
+        /// The highest Fourier harmonic to compute
+        #[arg(long, default_value_t = 12)]
+        harmonics: usize,
+
+        /// Write the fraction of the mass in each angular bin to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Write the amplitude and phase of each harmonic to this CSV file
+        #[arg(long)]
+        harmonics_out: Option<String>,
+    },
+
+    /// Write an animation of a saved grid growing, replayed from the order its particles stuck in
+    Animate {
+        /// The grid file to animate
+        file: String,
+
+        /// File to write the animation to. Files ending in .gif are GIFs, anything else is an animated PNG
+        out: String,
+
+        /// Number of frames
+        #[arg(long, default_value_t = 100)]
+        frames: usize,
+
+        /// Milliseconds each frame is shown for
+        #[arg(long, default_value_t = 40)]
+        delay: u16,
+
+        /// Each particle is a square this many pixels wide
+        #[arg(long, default_value_t = 1)]
+        scale: usize,
+    },
+
+    /// Write a saved grid to an SVG file, with particles colored as they're drawn on screen.
+    /// Colors come from --color and --background-color before the command, or from --theme
+    Svg {
+        /// The grid file to export
+        file: String,
+
+        /// The SVG file to write
+        out: String,
+
+        /// How to draw particles. Options are: circle, square
+        #[arg(long, default_value = "circle")]
+        shape: String,
+
+        /// Draw each horizontal run of neighboring particles with the same color as one shape. Much smaller files
+        #[arg(long, default_value_t = false)]
+        merge: bool,
+
+        /// Width of each particle in the SVG's pixels
+        #[arg(long, default_value_t = 4.0)]
+        scale: f64,
+
+        /// Color particles by age with this theme (case insensitive), or none to use --color.
+        /// Options are: seafoam, lemon, forest, candy, christmas, creamsicle, vibrant
+        #[arg(long)]
+        theme: Option<String>,
+    },
+
+    /// Write a saved grid's aggregation tree as pen strokes for plotters and laser cutters.
+    /// Files ending in .hpgl or .plt are HPGL, anything else is SVG
+    Plot {
+        /// The grid file to export
+        file: String,
+
+        /// The file to write
+        out: String,
+
+        /// Millimeters between the centers of neighboring particles
+        #[arg(long, default_value_t = 0.5)]
+        cell_size: f64,
+
+        /// Width of the lines in SVGs, in millimeters
+        #[arg(long, default_value_t = 0.3)]
+        pen_width: f64,
+    },
+
+    /// Describe a grid file: its format version, size, particle count and the settings that grew it
+    Info {
+        /// The grid file to describe
+        file: String,
+    },
+
+    /// Convert a grid file to another format, picked by extension: .pbm, .pgm, .txt (ASCII art) or anything else but
+    /// .png for the compressed grid format
+    Convert {
+        /// The grid file to read
+        file: String,
+
+        /// The file to write
+        out: String,
+    },
+
+    /// Export a saved grid for analysis in Python: <OUT>_mask.npy and <OUT>_ids.npy arrays, and a list of particles in
+    /// <OUT>_particles.csv
+    Numpy {
+        /// The grid file to export
+        file: String,
+
+        /// Prefix of the files to write
+        out: String,
+
+        /// Event log of the run that grew the grid (see --event-log), to add when each particle stuck to the list
+        #[arg(long)]
+        events: Option<String>,
+    },
+
+    /// Write a saved grid as a binary STL for 3D printing: every particle is a column on a base plate
+    Stl {
+        /// The grid file to export
+        file: String,
+
+        /// The STL file to write
+        out: String,
+
+        /// Width of each cell in millimeters
+        #[arg(long, default_value_t = 1.0)]
+        cell_size: f64,
+
+        /// Thickness of the base plate in millimeters
+        #[arg(long, default_value_t = 2.0)]
+        base: f64,
+
+        /// Height of columns above the plate in millimeters. With --by-id, the height of the newest particle
+        #[arg(long, default_value_t = 5.0)]
+        column: f64,
+
+        /// Scale column heights with particle id, so the newest particles are tallest
+        #[arg(long, default_value_t = false)]
+        by_id: bool,
+
+        /// Height of the seeds' columns with --by-id, in millimeters
+        #[arg(long, default_value_t = 0.5)]
+        min_column: f64,
+    },
+
+    /// Label the connected components of a saved grid, and report which seed each particle grew from and when the
+    /// seeds' clusters merged
+    Components {
+        /// The grid file to analyze
+        file: String,
+
+        /// Write each seed's position, mass and merge time to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Write every particle's connected component and owning seed to this CSV file
+        #[arg(long)]
+        labels: Option<String>,
+    },
+
+    /// Estimate the harmonic measure (growth probability) of a saved grid with probe walkers, and compute its
+    /// generalized dimensions D_q and f(α) spectrum
+    Harmonic {
+        /// The grid file to analyze
+        file: String,
+
+        /// Number of probe walkers to launch
+        #[arg(short, long, default_value_t = 10000)]
+        probes: usize,
+
+        /// Only spawn probes further than this from the center of the grid
+        #[arg(long)]
+        spawn_radius: Option<usize>,
+
+        /// The smallest box edge length to coarse-grain the measure with
+        #[arg(long, default_value_t = 1)]
+        min_size: usize,
+
+        /// The largest box edge length to coarse-grain the measure with. Defaults to a quarter of the shortest grid edge
+        #[arg(long)]
+        max_size: Option<usize>,
+
+        /// The lowest moment order q
+        #[arg(long, default_value_t = -2.0, allow_negative_numbers = true)]
+        min_q: f64,
+
+        /// The highest moment order q
+        #[arg(long, default_value_t = 6.0, allow_negative_numbers = true)]
+        max_q: f64,
+
+        /// Spacing between moment orders
+        #[arg(long, default_value_t = 0.5)]
+        q_step: f64,
+
+        /// Write τ(q), D_q, α(q) and f(α) for every q to this CSV file
+        #[arg(short, long)]
+        out: Option<String>,
+
+        /// Write the number of probes that stuck at each site to this CSV file
+        #[arg(long)]
+        measure: Option<String>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, EnumIter)]
@@ -88,6 +512,21 @@ pub enum GridType {
     Circle,
 }
 
+impl GridType {
+    /// The grid type a --grid-type name stands for (case insensitive), if it's one of them
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "center" => Some(GridType::Center),
+            "bottomedge" => Some(GridType::BottomEdge),
+            "alledges" => Some(GridType::AllEdges),
+            "fourdots" => Some(GridType::FourDots),
+            "randfive" => Some(GridType::RandFive),
+            "circle" => Some(GridType::Circle),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for GridType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -125,29 +564,44 @@ impl Default for DlaConfig {
         DlaConfig {
             width: 400,
             height: None,
-            scale_factor: 1.0, // meta argument
+            scale_factor: 1.25, // meta argument
             particles: 10_000,
             color_style: None,
             benchmark: false, // This is a meta-arg that's only relevant at the top level
             from_file: None,
             to_file: None,
+            image: None,
+            image_scale: 1,
+            animation: None,
+            frames: 100,
+            frame_every: None,
+            frame_delay: 40,
+            stream: None,
+            stream_format: None,
+            stream_every: 10,
+            stream_every_updates: None,
+            stream_fps: 30,
             grid_type: None,
             color: None,
             background_color: None,
             view: None,
+            noise_reduction: None,
+            temperature: None,
+            max_walk: None,
+            evaporation: None,
+            stickiness: None,
+            spawn_radius: None,
+            seed: None,
+            event_log: None,
+            command: None,
         }
     }
 }
 
 impl DlaConfig {
     pub fn view(&self) -> View {
-        // if a from file is specified, use View::End so that DlaGrid::Show doesn't generate on top of the grid
-        if self.from_file.is_some() {
-            return View::End;
-        }
-
         let default_view: View = View::Live;
-        if let Some(view_type) = &self.view {
+        let view = if let Some(view_type) = &self.view {
             match view_type.to_ascii_lowercase().as_str() {
                 "end" => View::End,
                 "skip" => View::Skip,
@@ -155,6 +609,13 @@ impl DlaConfig {
             }
         } else {
             default_view
+        };
+
+        // if a from file is specified, use View::End so that DlaGrid::Show doesn't generate on top of the grid. Skip
+        // still skips the window, for writing a saved grid out without a display
+        match view {
+            View::Live if self.from_file.is_some() => View::End,
+            view => view,
         }
     }
 
@@ -162,16 +623,120 @@ impl DlaConfig {
     // yes, this is a workaround to me not knowing how to tell clap to use a default str.
     pub fn grid_type(&self) -> GridType {
         let default_grid_type: GridType = GridType::Center;
-        if let Some(grid_type) = &self.grid_type {
-            match grid_type.to_ascii_lowercase().as_str() {
-                "bottomedge" => GridType::BottomEdge,
-                "alledges" => GridType::AllEdges,
-                "fourdots" => GridType::FourDots,
-                _ => default_grid_type,
+        self.grid_type
+            .as_deref()
+            .and_then(GridType::from_name)
+            .unwrap_or(default_grid_type)
+    }
+
+    /// Check that the simulation settings are in range. Returns a message about the first one that isn't
+    pub fn validate(&self) -> Result<(), String> {
+        // the grid can't be empty
+        if self.width == 0 || self.height == Some(0) {
+            return Err("width and height must be at least 1".to_string());
+        }
+        if self.noise_reduction == Some(0) {
+            return Err("noise-reduction must be at least 1".to_string());
+        }
+        if self.image_scale == 0 {
+            return Err("image-scale must be at least 1".to_string());
+        }
+        if self.frames == 0 {
+            return Err("frames must be at least 1".to_string());
+        }
+        if self.frame_every == Some(0) {
+            return Err("frame-every must be at least 1".to_string());
+        }
+        if let Some(temperature) = self.temperature {
+            if !(temperature > 0.0 && temperature.is_finite()) {
+                return Err(format!("temperature must be above 0, not {}", temperature));
+            }
+        }
+        if let Some(max_walk) = self.max_walk {
+            // walkers would expire as soon as they spawn
+            if max_walk == 0 {
+                return Err("max-walk must be at least 1".to_string());
             }
-        } else {
-            default_grid_type
         }
+        if let Some(evaporation) = self.evaporation {
+            // at 1 every walker evaporates before it can stick
+            if !(0.0..1.0).contains(&evaporation) {
+                return Err(format!(
+                    "evaporation must be at least 0 and below 1, not {}",
+                    evaporation
+                ));
+            }
+        }
+        if let Some(stickiness) = self.stickiness {
+            // at 0 no walker ever sticks
+            if !(stickiness > 0.0 && stickiness <= 1.0) {
+                return Err(format!(
+                    "stickiness must be above 0 and at most 1, not {}",
+                    stickiness
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The simulation flags that are set, as they'd be typed on the command line. Parsing them gives back this config,
+    /// without the command and the flags that only affect how a run is shown or saved
+    pub fn to_args(&self) -> String {
+        let mut args = vec![
+            format!("--width {}", self.width),
+            format!("--particles {}", self.particles),
+        ];
+        let optional = [
+            ("height", self.height.map(|v| v.to_string())),
+            ("grid-type", self.grid_type.clone()),
+            ("color", self.color.clone()),
+            ("background-color", self.background_color.clone()),
+            (
+                "noise-reduction",
+                self.noise_reduction.map(|v| v.to_string()),
+            ),
+            ("temperature", self.temperature.map(|v| v.to_string())),
+            ("max-walk", self.max_walk.map(|v| v.to_string())),
+            ("evaporation", self.evaporation.map(|v| v.to_string())),
+            ("stickiness", self.stickiness.map(|v| v.to_string())),
+            ("spawn-radius", self.spawn_radius.map(|v| v.to_string())),
+            ("seed", self.seed.map(|v| v.to_string())),
+        ];
+        for (flag, value) in optional {
+            if let Some(value) = value {
+                // color names can have spaces, and parse_color ignores them
+                args.push(format!("--{} {}", flag, value.replace(' ', "")));
+            }
+        }
+        args.join(" ")
+    }
+
+    /// The particle color, if the user picked one
+    pub fn color(&self) -> Option<ColorName> {
+        self.color.as_deref().and_then(Self::parse_color)
+    }
+
+    /// The background color, if the user picked one
+    pub fn background_color(&self) -> Option<ColorName> {
+        self.background_color.as_deref().and_then(Self::parse_color)
+    }
+
+    /// Parse a theme name (case insensitive). "none" is Some(None)
+    pub fn parse_theme(name: &str) -> Option<Option<Theme>> {
+        let name = name.to_ascii_lowercase();
+        if name == "none" {
+            return Some(None);
+        }
+        Theme::iter()
+            .find(|theme| theme.to_string().to_ascii_lowercase() == name)
+            .map(Some)
+    }
+
+    /// Case insensitive, and spaces don't matter so "lightblue" works for "Light Blue"
+    fn parse_color(name: &str) -> Option<ColorName> {
+        let name = name.replace(' ', "").to_ascii_lowercase();
+        ColorName::iter()
+            .find(|color| color.to_string().replace(' ', "").to_ascii_lowercase() == name)
     }
 
     pub fn color_style(&self) -> ColorStyle {
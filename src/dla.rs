@@ -1,29 +1,43 @@
+use crate::analysis::MassRadius;
+use crate::animation::Recorder;
+use crate::components::{MergeEvent, Ownership};
 use crate::config::{DlaConfig, GridType};
+use crate::events::{EventLog, StickEvent};
 use crate::grid::Grid;
+use crate::gridfile::Parameters;
+use crate::image;
+use crate::portable::Format;
+use crate::stream::{FrameStream, StreamFormat, Trigger};
+use crate::tree::{self, AggregationTree};
 use colored::Colorize;
 use core::time;
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::io::Write;
 use thousands::Separable;
 
 use std::sync::{Arc, Mutex};
 use std::thread;
 
-use crate::colors::{get_gradients, Color, ColorName, Theme};
+use crate::colors::{get_color_name, get_gradients, Color, ColorName, Theme, SEED_COLORS};
 use crate::gui::{PAUSE_BUTTON_TEXT, UNPAUSE_BUTTON_TEXT};
 
 // so the gui can stay in sync
 pub const DEFAULT_PART_CLR: ColorName = ColorName::Seafoam;
 pub const DEFAULT_BACK_CLR: ColorName = ColorName::Black;
 pub const DEFAULT_THEME: Theme = Theme::Seafoam;
+/// Name and version of the program, recorded in the files it writes
+pub const SOFTWARE: &str = concat!("dla ", env!("CARGO_PKG_VERSION"));
 
-use log::debug;
+use log::{debug, info};
 #[derive(Clone, Debug)]
 struct Particle {
     /// Does the particle exist?
     exists: bool,
     /// The current position of the particle (x, y)
     pos: (usize, usize),
+    /// How many steps the particle has walked since it spawned
+    steps: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -62,19 +76,81 @@ pub(crate) struct Dla {
     do_resize: bool,
 
     theme: Option<Theme>,
+    /// Noise reduction: the number of times walkers need to touch a perimeter site before it fills.
+    /// Walkers that touch a site before its counter reaches this value are removed. None is classic DLA (every touch
+    /// fills the site), which is equivalent to a value of 1.
+    noise_reduction: Option<u32>,
+    /// Per cell hit counters for noise reduction. Indexed the same way as the grid cells
+    hits: Vec<u32>,
+    /// Temperature-like parameter for surface relaxation. When set, every time a particle sticks a random stuck
+    /// particle may hop along the surface or detach, with probability exp(-n / temperature) where n is its number of
+    /// filled neighbors. None disables relaxation, so stuck particles never move.
+    temperature: Option<f64>,
+    /// Grid indices of the particles that stuck during the simulation (seeds aren't included, they never move).
+    /// These are the candidates for relaxation
+    mobile: Vec<usize>,
+    /// The id of the most recently stuck particle. This only ever increases, so ids stay unique even when particles
+    /// detach and stick again
+    last_id: usize,
+    /// Walkers are removed after walking this many steps without sticking. None lets them walk forever
+    max_walk: Option<u64>,
+    /// Probability that a walker evaporates (is removed) on any given step. None disables evaporation
+    evaporation: Option<f64>,
+    /// Probability that a walker sticks each time it touches the aggregate. If it doesn't, it keeps walking. None is
+    /// classic DLA, where walkers always stick, which is equivalent to a value of 1
+    stickiness: Option<f64>,
+    /// The number of walkers that were removed by max_walk or evaporation
+    evaporated: usize,
+    /// When set, every particle that sticks is recorded here. Shared so that cloning the simulation doesn't try to
+    /// duplicate the open file
+    event_log: Option<Arc<Mutex<EventLog>>>,
+    /// Replay support. When set, only particles with an id <= this value are drawn. Seeds (id 0) are always drawn.
+    /// Since ids increase in the order particles stuck, this shows the aggregate at an earlier point in its growth
+    timeline: Option<usize>,
+    /// Radius of gyration and maximum radius of the particles that stuck, updated as they stick
+    mass_radius: MassRadius,
+    /// The aggregation tree as it grows. Maps each particle id to the id of the particle it stuck to (see
+    /// tree::parent_of for how ties are broken). Seeds all have id 0. None if the particle detached
+    parents: Vec<Option<usize>>,
+    /// How many particles have each id as their parent. Relaxation only moves particles without any, so nothing is
+    /// left hanging from a particle that moved
+    children: Vec<usize>,
+    /// Which seed each particle grew from, and when the seeds' clusters merged
+    ownership: Ownership,
+    /// Color particles by the seed that owns them instead of by color or theme
+    color_by_seed: bool,
+    /// All randomness in the simulation comes from here, so runs with the same seed grow the same aggregate
+    rng: StdRng,
+    /// The seed rng started from. Saved in images so their aggregate can be grown again
+    seed: u64,
+    /// When set, the grid is written to this file once the simulation completes
+    to_file: Option<String>,
+    /// When set, the grid is drawn to this PNG file once the simulation completes
+    image: Option<String>,
+    /// Each particle is a square this many pixels wide in images
+    image_scale: usize,
+    /// When set, an animation of the grid growing is written once the simulation completes
+    animation: Option<Recorder>,
+    /// When set, frames are streamed out as the simulation runs, whenever the trigger fires
+    stream: Option<(Trigger, Arc<Mutex<FrameStream>>)>,
 }
 
 impl Default for Dla {
     fn default() -> Self {
         let (width, height) = (400, 400);
         let grid_type = GridType::Center;
-        let grid = Grid::from(grid_type, width, height);
+        let seed = rand::random();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let grid = Grid::from_rng(grid_type, width, height, &mut rng);
         let stuck_particles = grid.stuck_particles();
+        let hits = vec![0; grid.cells.len()];
+        let ownership = Ownership::from_seeds(&grid);
         Self {
             grid,
             cur_part: Particle {
                 exists: false,
                 pos: (0, 0),
+                steps: 0,
             },
             stuck_particles,
             is_complete: false,
@@ -87,6 +163,29 @@ impl Default for Dla {
             spawn_radius: None,
             do_resize: false,
             theme: Some(DEFAULT_THEME),
+            noise_reduction: None,
+            hits,
+            temperature: None,
+            mobile: Vec::new(),
+            last_id: stuck_particles + 1,
+            max_walk: None,
+            evaporation: None,
+            stickiness: None,
+            evaporated: 0,
+            event_log: None,
+            timeline: None,
+            mass_radius: MassRadius::default(),
+            parents: Vec::new(),
+            children: Vec::new(),
+            ownership,
+            color_by_seed: false,
+            rng,
+            seed,
+            to_file: None,
+            image: None,
+            image_scale: 1,
+            animation: None,
+            stream: None,
         }
     }
 }
@@ -102,23 +201,37 @@ impl Dla {
             width
         };
 
-        let color: Color = DEFAULT_PART_CLR.get_color();
-        let background_color: Color = DEFAULT_BACK_CLR.get_color();
+        let color: Color = config.color().unwrap_or(DEFAULT_PART_CLR).get_color();
+        let background_color: Color = config
+            .background_color()
+            .unwrap_or(DEFAULT_BACK_CLR)
+            .get_color();
 
         let grid_type = config.grid_type(); // this will give us a default if user didn't specify
 
-        let grid: Grid = Grid::from(grid_type, width, height);
+        // pick a seed even if the user didn't, so it can be saved and the run repeated
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let grid: Grid = Grid::from_rng(grid_type, width, height, &mut rng);
 
         // number of stuck particles depends on grid type
         // doing this work up front is slower, but it's a 1 time cost that means we don't need to maintain #particles
         // stuck for each grid type separately
         let stuck_particles = grid.stuck_particles();
+        let hits = vec![0; grid.cells.len()];
+        let ownership = Ownership::from_seeds(&grid);
+        if config.event_log.is_some() && config.temperature.is_some() {
+            eprintln!(
+                "Relaxation moves aren't logged, so the event log won't replay to the final grid"
+            );
+        }
 
         Self {
             grid,
             cur_part: Particle {
                 exists: false,
                 pos: (0, 0),
+                steps: 0,
             },
             stuck_particles,
             is_complete: false,
@@ -128,12 +241,61 @@ impl Dla {
             empty_color: background_color,
             paused: true,
             grid_type,
-            spawn_radius: None,
+            spawn_radius: config.spawn_radius,
             do_resize: false,
-            theme: Some(DEFAULT_THEME),
+            // picking a color means the user wants to see it, instead of the theme
+            theme: if config.color.is_some() {
+                None
+            } else {
+                Some(DEFAULT_THEME)
+            },
+            noise_reduction: config.noise_reduction,
+            hits,
+            temperature: config.temperature,
+            mobile: Vec::new(),
+            last_id: stuck_particles + 1,
+            max_walk: config.max_walk,
+            evaporation: config.evaporation,
+            stickiness: config.stickiness,
+            evaporated: 0,
+            event_log: config.event_log.as_deref().and_then(Self::open_event_log),
+            timeline: None,
+            mass_radius: MassRadius::default(),
+            parents: Vec::new(),
+            children: Vec::new(),
+            ownership,
+            color_by_seed: false,
+            rng,
+            seed,
+            to_file: config.to_file.clone(),
+            image: config.image.clone(),
+            image_scale: config.image_scale,
+            animation: config.animation.as_deref().map(|file_name| {
+                Recorder::new(
+                    file_name,
+                    config.frames,
+                    config.frame_every,
+                    config.frame_delay,
+                    config.image_scale,
+                )
+            }),
+            stream: Self::open_stream(config),
         }
     }
 
+    /// A completed simulation of an existing grid, for example one read from a file. Everything else is default
+    pub(crate) fn from_grid(grid: Grid) -> Self {
+        let mut dla = Self::default();
+        dla.set_grid(grid);
+        dla
+    }
+
+    /// Restart the random number generator from `seed`
+    pub(crate) fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
     /// Run the simulation until all particles have stuck
     pub fn run(&mut self) {
         while !self.is_complete {
@@ -144,12 +306,13 @@ impl Dla {
     fn swap_grid_type(&mut self, new_grid_type: GridType, size: Option<(u32, u32)>) {
         // swapping grid type defaults to use the current width
         let (width, height) = size.unwrap_or((self.grid.width as u32, self.grid.height as u32));
-        let new_grid: Grid = Grid::from(new_grid_type, width, height);
+        let new_grid: Grid = Grid::from_rng(new_grid_type, width, height, &mut self.rng);
 
         self.grid = new_grid;
         self.cur_part = Particle {
             exists: false,
             pos: (0, 0),
+            steps: 0,
         }; // reset
         self.stuck_particles = self.grid.stuck_particles();
         self.is_complete = false; // reset
@@ -160,6 +323,19 @@ impl Dla {
                           // to_file same
         self.paused = true; // make them restart with the new grid
         self.grid_type = new_grid_type;
+        self.hits = vec![0; self.grid.cells.len()]; // reset
+        self.mobile.clear(); // reset
+        self.last_id = self.stuck_particles + 1; // reset
+        self.evaporated = 0; // reset
+        self.timeline = None; // reset
+        self.mass_radius = MassRadius::default(); // reset
+        self.parents.clear(); // reset
+        self.children.clear(); // reset
+        self.ownership = Ownership::from_seeds(&self.grid); // reset
+        self.restart_event_log();
+        if let Some(animation) = self.animation.as_mut() {
+            animation.restart();
+        }
     }
 
     fn swap_particle_color(&mut self, new_color: ColorName) {
@@ -176,6 +352,12 @@ impl Dla {
     /// stick to one another. Return true if one of the neighbors of (x, y) is FILLED,
     /// and false otherwise.
     fn should_stick(&self, x: usize, y: usize) -> bool {
+        self.touching_neighbor(x, y).is_some()
+    }
+
+    /// Returns the first FILLED neighbor of (x, y), if there is one. Neighbors are checked in the order
+    /// get_neighbors returns them, so the result is deterministic.
+    fn touching_neighbor(&self, x: usize, y: usize) -> Option<(usize, usize)> {
         // use isize here so that we can go off grid. the if statement in the update loop makes sure we only look at points
         // that fit in the grid
         let neighbors = self.get_neighbors(x, y);
@@ -185,9 +367,130 @@ impl Dla {
             if self.valid_grid_pos((*nx, *ny))
                 && self.grid.filled(self.get_idx(*nx as usize, *ny as usize))
             {
+                return Some((*nx as usize, *ny as usize));
+            }
+        }
+        None
+    }
+
+    /// Register a walker touching the perimeter site at idx. Returns whether the site should fill.
+    ///
+    /// Without noise reduction every touch fills the site. With noise reduction, the site's hit counter is
+    /// incremented and it only fills once the counter reaches the threshold.
+    fn register_hit(&mut self, idx: usize) -> bool {
+        if let Some(threshold) = self.noise_reduction {
+            self.hits[idx] += 1;
+            self.hits[idx] >= threshold
+        } else {
+            true
+        }
+    }
+
+    /// Number of filled neighbors of (x, y)
+    fn coordination(&self, x: usize, y: usize) -> usize {
+        self.get_neighbors(x, y)
+            .iter()
+            .filter(|(nx, ny)| {
+                self.valid_grid_pos((*nx, *ny))
+                    && self.grid.filled(self.get_idx(*nx as usize, *ny as usize))
+            })
+            .count()
+    }
+
+    /// Surface relaxation. Pick a random stuck particle and, with a probability based on how many filled neighbors
+    /// it has, move it to a random empty neighbor. Only tips of the aggregation tree move: a particle that others
+    /// stuck to stays put, so the tree and ownership always match the grid.
+    ///
+    /// If the particle touches an older particle at its new position it hopped along the surface and stays stuck
+    /// (keeping its id), with that as its new parent. Otherwise it detached: it becomes the current walker and is no
+    /// longer counted as stuck. Must only be called when there's no walker, since a detached particle takes its place.
+    fn relax(&mut self, temperature: f64) {
+        if self.mobile.is_empty() || self.cur_part.exists {
+            return;
+        }
+
+        let i = self.rng.gen_range(0..self.mobile.len());
+        let idx = self.mobile[i];
+        let (x, y) = (idx % self.grid.width, idx / self.grid.width);
+
+        let id = self.grid.cells[idx].id;
+        if self.children.get(id).is_some_and(|&n| n > 0) {
+            return; // other particles hang from it
+        }
+        if self.surrounded(x, y) {
+            return; // there's nowhere to go
+        }
+        let neighbors = self.coordination(x, y);
+        let move_probability = (-(neighbors as f64) / temperature).exp();
+        if self.rng.gen::<f64>() >= move_probability {
+            return;
+        }
+
+        // the particle is moving, so it's no longer at the old location
+        self.grid.set_fill(idx, false);
+        self.grid.cells[idx].id = 0;
+        self.ownership.remove(idx);
+
+        let (newx, newy) = self.random_walk(x, y);
+        let new_idx = self.get_idx(newx, newy);
+        self.grid.set_fill(new_idx, true);
+        self.grid.cells[new_idx].id = id;
+
+        let parent = tree::parent_of(&self.grid, new_idx);
+        if parent.is_some() {
+            // hopped along the surface. It's attached to something else now
+            self.mobile[i] = new_idx;
+            self.set_parent(id, parent);
+            let merges = self.ownership.add(&self.grid, new_idx);
+            self.report_merges(&merges);
+            let dist = self.grid.dist_to_center(newx, newy);
+            self.mass_radius.move_particle((x, y), (newx, newy), dist);
+        } else {
+            // detached, or only touching particles younger than it. It's a walker again, which fills its cell without
+            // an id
+            self.grid.cells[new_idx].id = 0;
+            self.mobile.swap_remove(i);
+            self.stuck_particles -= 1;
+            self.set_parent(id, None);
+            self.mass_radius.remove(x, y);
+            self.cur_part = Particle {
+                exists: true,
+                pos: (newx, newy),
+                steps: 0,
+            };
+        }
+    }
+
+    /// Returns whether every neighbor of (x, y) inside the grid is filled
+    fn surrounded(&self, x: usize, y: usize) -> bool {
+        let valid_neighbors = self
+            .get_neighbors(x, y)
+            .iter()
+            .filter(|n| self.valid_grid_pos(**n))
+            .count();
+        self.coordination(x, y) == valid_neighbors
+    }
+
+    /// Returns whether a walker touching the aggregate at (x, y) sticks. With stickiness set, it only sticks with that
+    /// probability and otherwise keeps walking. A walker with nowhere left to walk always sticks
+    fn sticks_on_contact(&mut self, x: usize, y: usize) -> bool {
+        match self.stickiness {
+            Some(stickiness) => self.rng.gen::<f64>() < stickiness || self.surrounded(x, y),
+            None => true,
+        }
+    }
+
+    /// Returns whether the current walker has reached the end of its life, either because it walked the maximum
+    /// number of steps or because it evaporated this step
+    fn walker_expired(&mut self) -> bool {
+        if let Some(max_walk) = self.max_walk {
+            if self.cur_part.steps >= max_walk {
                 return true;
             }
         }
+        if let Some(evaporation) = self.evaporation {
+            return self.rng.gen::<f64>() < evaporation;
+        }
         false
     }
 
@@ -254,7 +557,7 @@ impl Dla {
             panic!("No neighbors found for random walk! This is a bug");
         }
 
-        let neighbor_idx = thread_rng().gen_range(0..num_neighbors);
+        let neighbor_idx = self.rng.gen_range(0..num_neighbors);
 
         (
             *valid_neighbors[neighbor_idx].0 as usize,
@@ -262,6 +565,42 @@ impl Dla {
         )
     }
 
+    /// Launch `probes` walkers at the grid and count where each one would stick, without changing the grid.
+    ///
+    /// Probes spawn and walk exactly like walkers do in update, but they're never filled in, so every probe sees the
+    /// same aggregate. Each probe is counted at the empty perimeter site it would fill. Evaporation and noise
+    /// reduction don't apply. A probe that walks max_walk steps (or 100 steps per grid cell without it) is dropped
+    /// without being counted.
+    ///
+    /// Returns the number of probes that stuck at each grid index. All 0 if there's no aggregate to stick to.
+    pub fn probe_growth_sites(&mut self, probes: usize) -> Vec<u64> {
+        let mut counts = vec![0; self.grid.cells.len()];
+        if !self.grid.cells.iter().any(|cell| cell.filled) {
+            return counts;
+        }
+        let max_steps = self.max_walk.unwrap_or(100 * self.grid.cells.len() as u64);
+        // random_loc marks the simulation complete when it can't find anywhere to spawn
+        let was_complete = self.is_complete;
+        self.is_complete = false;
+        for _ in 0..probes {
+            let (mut x, mut y) = self.random_loc();
+            if self.is_complete {
+                eprintln!("No room left to spawn probes! Stopping early");
+                break;
+            }
+            let mut steps = 0;
+            while !self.should_stick(x, y) && steps < max_steps {
+                (x, y) = self.random_walk(x, y);
+                steps += 1;
+            }
+            if self.should_stick(x, y) {
+                counts[self.get_idx(x, y)] += 1;
+            }
+        }
+        self.is_complete = was_complete;
+        counts
+    }
+
     fn flush_stdout() {
         let mut stdout = std::io::stdout();
         stdout.flush().unwrap();
@@ -283,6 +622,14 @@ impl Dla {
         // This if/else block MUST result in an updated location for cur_part
         if self.cur_part.exists {
             let (oldx, oldy) = self.cur_part.pos;
+            if self.walker_expired() {
+                // remove the walker. A new one will spawn next update
+                let old_idx = self.get_idx(oldx, oldy);
+                self.grid.set_fill(old_idx, false);
+                self.cur_part.exists = false;
+                self.evaporated += 1;
+                return;
+            }
             let (newx, newy) = self.random_walk(oldx, oldy);
 
             // the particle is no longer at the old location
@@ -291,37 +638,299 @@ impl Dla {
 
             // update cur particle
             self.cur_part.pos = (newx, newy);
+            self.cur_part.steps += 1;
         } else {
             // spawn a new particle at a random location
             let (startx, starty) = self.random_loc();
             self.cur_part = Particle {
                 exists: true,
                 pos: (startx, starty),
+                steps: 0,
             };
         }
 
+        // get the idx of the current partcle so we can mark it as full in the vector
+        let idx = self.get_idx(self.cur_part.pos.0, self.cur_part.pos.1);
+        let mut fill = true;
+        let mut stuck = false;
+
         // we either moved, or spawned. In both cases we need to update our state if the particle should stick.
-        if self.should_stick(self.cur_part.pos.0, self.cur_part.pos.1) {
+        let (x, y) = self.cur_part.pos;
+        if self.should_stick(x, y) && self.sticks_on_contact(x, y) {
             self.cur_part.exists = false;
-            self.stuck_particles += 1;
-            // get the idx of the current partcle, and mark it as full in the vector
-            let idx = self.get_idx(self.cur_part.pos.0, self.cur_part.pos.1);
-            self.grid.cells[idx].id = self.stuck_particles + 1;
+            if self.register_hit(idx) {
+                self.stuck_particles += 1;
+                self.last_id += 1;
+                self.grid.cells[idx].id = self.last_id;
+                self.mobile.push(idx);
+                self.mass_radius.add(x, y, self.grid.dist_to_center(x, y));
+                stuck = true;
+
+                // it touches something, and everything it touches is older, so it always has a parent
+                let parent = tree::parent_of(&self.grid, idx)
+                    .expect("A particle that stuck should have a parent! This is a bug");
+                self.set_parent(self.last_id, Some(parent));
+                let merges = self.ownership.add(&self.grid, idx);
+                self.report_merges(&merges);
+
+                self.log_stick(StickEvent {
+                    id: self.last_id,
+                    pos: self.cur_part.pos,
+                    update: self.updates,
+                    steps: self.cur_part.steps,
+                    neighbor: (parent % self.grid.width, parent / self.grid.width),
+                });
+            } else {
+                // noise reduction: the site hasn't been hit enough times yet. The walker is removed without
+                // filling it
+                fill = false;
+            }
         }
 
         // we either moved a particle or spawned a particle. The current particle's location needs to be filled to prep
         // for the next iteration
-        let idx = self.get_idx(self.cur_part.pos.0, self.cur_part.pos.1);
-        self.grid.set_fill(idx, true);
+        if fill {
+            self.grid.set_fill(idx, true);
+        }
+
+        if let (true, Some(temperature)) = (stuck, self.temperature) {
+            self.relax(temperature);
+        }
+
+        if stuck
+            && self
+                .animation
+                .as_ref()
+                .is_some_and(|a| a.wants_frame(self.mobile.len()))
+        {
+            let frame = self.render();
+            let (width, height) = self.size();
+            if let Some(animation) = self.animation.as_mut() {
+                animation.capture(frame, width, height);
+            }
+        }
+
+        let streamed = self
+            .stream
+            .as_ref()
+            .is_some_and(|(trigger, _)| trigger.fires(self.updates, self.stuck_particles, stuck));
+        if streamed {
+            self.stream_frame();
+        }
 
         if self.stuck_particles >= self.particles {
             self.is_complete = true; // flag us as done so somebody running the simulation knows :D
+            self.flush_event_log();
+            // the stream always ends on the finished grid
+            if !streamed {
+                self.stream_frame();
+            }
+            self.flush_stream();
+            self.write_outputs();
+        }
+    }
+
+    /// Write the finished grid to --to-file, --image and --animation. Only once, so the files aren't overwritten if
+    /// the simulation is reset and runs again
+    pub fn write_outputs(&mut self) {
+        if let Some(to_file) = self.to_file.take() {
+            self.grid.to_file(&to_file, &self.parameters());
+        }
+        if let Some(image) = self.image.take() {
+            self.save_image(&image, self.image_scale);
+        }
+        if let Some(animation) = self.animation.take() {
+            animation.finish(self);
+        }
+    }
+
+    /// Print what happened to the walkers of a finished run, along with the aggregate's size
+    pub fn print_summary(&self) {
+        // don't mix messages into frames going to standard output
+        if self.stream_to_stdout() {
+            return;
+        }
+        // every walker that stuck and is still stuck is mobile, seeds never are
+        let summary = [
+            ("Walkers stuck: ", self.mobile.len().separate_with_commas()),
+            (
+                "   Walkers evaporated: ",
+                self.evaporated.separate_with_commas(),
+            ),
+            ("   Updates: ", self.updates.separate_with_commas()),
+            (
+                "   Radius of gyration: ",
+                format!("{:.2}", self.mass_radius.radius_of_gyration()),
+            ),
+        ];
+        for (label, value) in summary.iter() {
+            print!("{}{}", label.to_string().bold().blue(), value.green());
+        }
+        println!();
+    }
+
+    fn report_merges(&self, merges: &[MergeEvent]) {
+        // logged rather than printed, so they don't interleave between ensemble workers or mix into streamed frames
+        for merge in merges {
+            info!(
+                "Clusters {} and {} merged at particle {} (masses {} and {})",
+                merge.clusters.0, merge.clusters.1, merge.id, merge.masses.0, merge.masses.1
+            );
+        }
+    }
+
+    fn open_event_log(path: &str) -> Option<Arc<Mutex<EventLog>>> {
+        match EventLog::create(path) {
+            Ok(log) => {
+                eprintln!("Logging stuck particles to file: {}", path);
+                Some(Arc::new(Mutex::new(log)))
+            }
+            Err(err) => {
+                eprintln!("Error encountered creating event log {}: {:?}", path, err);
+                None
+            }
+        }
+    }
+
+    fn open_stream(config: &DlaConfig) -> Option<(Trigger, Arc<Mutex<FrameStream>>)> {
+        let path = config.stream.as_deref()?;
+        let format = match StreamFormat::from(config.stream_format.as_deref(), path) {
+            Some(format) => format,
+            None => {
+                eprintln!("Unknown stream format, options are raw and y4m. Not streaming");
+                return None;
+            }
+        };
+        let trigger = match config.stream_every_updates {
+            Some(n) => Trigger::Updates(n),
+            None => Trigger::Particles(config.stream_every),
+        };
+        match FrameStream::create(path, format, config.stream_fps) {
+            Ok(stream) => Some((trigger, Arc::new(Mutex::new(stream)))),
+            Err(err) => {
+                eprintln!(
+                    "Error encountered creating frame stream {}: {:?}",
+                    path, err
+                );
+                None
+            }
+        }
+    }
+
+    fn stream_to_stdout(&self) -> bool {
+        self.stream
+            .as_ref()
+            .is_some_and(|(_, stream)| stream.lock().unwrap().to_stdout())
+    }
+
+    /// Draw the grid and write it to the frame stream, if there is one. If writing fails, streaming is disabled
+    fn stream_frame(&mut self) {
+        let stream = match &self.stream {
+            Some((_, stream)) => Arc::clone(stream),
+            None => return,
+        };
+        let (width, height) = self.size();
+        let frame = self.render();
+        let mut guard = stream.lock().unwrap();
+        if let Err(err) = guard.write_frame(width, height, &frame) {
+            // standard output may be what broke, so this goes to standard error
+            eprintln!(
+                "Error encountered writing to frame stream {}: {:?}. Disabling it",
+                guard.path(),
+                err
+            );
+            std::mem::drop(guard);
+            self.stream = None;
+        }
+    }
+
+    fn flush_stream(&mut self) {
+        if let Some((_, stream)) = &self.stream {
+            let mut guard = stream.lock().unwrap();
+            if let Err(err) = guard.flush() {
+                eprintln!(
+                    "Error encountered flushing frame stream {}: {:?}",
+                    guard.path(),
+                    err
+                );
+            } else if !guard.to_stdout() {
+                eprintln!(
+                    "Streamed {} frames to file: {}",
+                    guard.frames(),
+                    guard.path()
+                );
+            }
+        }
+    }
+
+    /// Record that particle `id` is attached to the particle at grid index `parent`
+    fn set_parent(&mut self, id: usize, parent: Option<usize>) {
+        if self.parents.len() <= id {
+            self.parents.resize(id + 1, None);
+        }
+        if let Some(old) = self.parents[id] {
+            self.children[old] -= 1;
+        }
+        let parent = parent.map(|p| self.grid.cells[p].id);
+        if let Some(new) = parent {
+            if self.children.len() <= new {
+                self.children.resize(new + 1, 0);
+            }
+            self.children[new] += 1;
+        }
+        self.parents[id] = parent;
+    }
+
+    /// Record a stuck particle in the event log, if there is one. If writing fails, logging is disabled
+    fn log_stick(&mut self, event: StickEvent) {
+        if let Some(log) = &self.event_log {
+            let mut guard = log.lock().unwrap();
+            if let Err(err) = guard.record(&event) {
+                eprintln!(
+                    "Error encountered writing to event log {}: {:?}. Disabling it",
+                    guard.path(),
+                    err
+                );
+                std::mem::drop(guard);
+                self.event_log = None;
+            }
+        }
+    }
+
+    /// Truncate the event log so it only holds the run starting now
+    fn restart_event_log(&mut self) {
+        if let Some(log) = &self.event_log {
+            let mut guard = log.lock().unwrap();
+            if let Err(err) = guard.restart() {
+                eprintln!(
+                    "Error encountered restarting event log {}: {:?}. Disabling it",
+                    guard.path(),
+                    err
+                );
+                std::mem::drop(guard);
+                self.event_log = None;
+            }
+        }
+    }
+
+    fn flush_event_log(&mut self) {
+        if let Some(log) = &self.event_log {
+            let mut guard = log.lock().unwrap();
+            if let Err(err) = guard.flush() {
+                eprintln!(
+                    "Error encountered flushing event log {}: {:?}",
+                    guard.path(),
+                    err
+                );
+            }
         }
     }
 
     pub fn draw(&mut self, screen: &mut [u8]) {
         // both draw functions share a for-loop, but there's enough extra stuff for time-coloring I opted to separate them
-        if let Some(theme) = self.theme {
+        if self.color_by_seed {
+            self.draw_seeds(screen);
+        } else if let Some(theme) = self.theme {
             self.draw_theme(screen, theme);
         } else {
             self.draw_normal(screen);
@@ -332,20 +941,22 @@ impl Dla {
         let num_colors = 10; // should match the number of gradients we get below
         let bucket_size = self.stuck_particles / num_colors;
         let theme_colors: [Color; 10] = get_gradients(theme);
+        let last_visible = self.timeline.unwrap_or(usize::MAX);
         for (c, pix) in self.grid.cells.iter_mut().zip(screen.chunks_exact_mut(4)) {
-            let color = if c.filled {
+            let color = if c.filled && c.id <= last_visible {
                 let id = c.id;
-                let idx = if bucket_size == 0 {
-                    0 // avoid divide by 0 if we don't have any stuck particles yet
-                } else {
-                    let tmp = id / bucket_size;
-                    // dividing by 10 to get bucket size is imprecise. It won't divide perfectly, so some particles will
-                    // be outside the range. We put them in the last bucket. For ex. Particle 100/100 will map to index 10
-                    // when it should be 9.
-                    if tmp >= num_colors {
-                        num_colors - 1
-                    } else {
-                        tmp
+                // checked_div avoids a divide by 0 if we don't have any stuck particles yet
+                let idx = match id.checked_div(bucket_size) {
+                    None => 0,
+                    Some(tmp) => {
+                        // dividing by 10 to get bucket size is imprecise. It won't divide perfectly, so some particles
+                        // will be outside the range. We put them in the last bucket. For ex. Particle 100/100 will map
+                        // to index 10 when it should be 9.
+                        if tmp >= num_colors {
+                            num_colors - 1
+                        } else {
+                            tmp
+                        }
                     }
                 };
                 theme_colors[idx]
@@ -356,9 +967,72 @@ impl Dla {
         }
     }
 
+    /// Draw the grid into a new RGBA buffer, the same way it's drawn on screen
+    pub fn render(&mut self) -> Vec<u8> {
+        let mut frame = vec![0; self.grid.cells.len() * 4];
+        self.draw(&mut frame);
+        frame
+    }
+
+    /// Draw the grid to a PNG file, with each particle a `scale` x `scale` square
+    pub fn save_image(&mut self, file_name: &str, scale: usize) {
+        eprintln!("Writing image out to file: {}", file_name);
+        let (width, height) = self.size();
+        let frame = self.render();
+        let text = self.image_text();
+        if let Err(err) = image::write_png(file_name, width, height, &frame, scale, &text) {
+            eprintln!("Error encountered writing image to file: {:?}", err);
+        }
+    }
+
+    /// Text chunks for images: everything needed to grow the run again, or read the grid back in from the image
+    pub fn image_text(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (image::SOFTWARE, SOFTWARE.to_string()),
+            (image::CONFIG, self.config().to_args()),
+            (image::SEED, self.seed.to_string()),
+            (image::PARTICLES, self.stuck_particles.to_string()),
+            (image::GRID_TYPE, format!("{:?}", self.grid_type)),
+            (
+                image::THEME,
+                self.theme
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|| "None".to_string()),
+            ),
+            (image::BACKGROUND, image::color_text(self.empty_color)),
+            (
+                image::IDS,
+                image::ids_text(&self.grid, self.timeline.unwrap_or(usize::MAX)),
+            ),
+        ]
+    }
+
+    /// Color each particle by the seed that owns it. Particles without an owner use the particle color
+    fn draw_seeds(&mut self, screen: &mut [u8]) {
+        let last_visible = self.timeline.unwrap_or(usize::MAX);
+        for (idx, (c, pix)) in self
+            .grid
+            .cells
+            .iter()
+            .zip(screen.chunks_exact_mut(4))
+            .enumerate()
+        {
+            let color = if c.filled && c.id <= last_visible {
+                match self.ownership.owner(idx) {
+                    Some(seed) => SEED_COLORS[seed % SEED_COLORS.len()],
+                    None => self.fill_color,
+                }
+            } else {
+                self.empty_color
+            };
+            pix.copy_from_slice(&color);
+        }
+    }
+
     fn draw_normal(&mut self, screen: &mut [u8]) {
+        let last_visible = self.timeline.unwrap_or(usize::MAX);
         for (c, pix) in self.grid.cells.iter_mut().zip(screen.chunks_exact_mut(4)) {
-            pix.copy_from_slice(if c.filled {
+            pix.copy_from_slice(if c.filled && c.id <= last_visible {
                 &self.fill_color
             } else {
                 &self.empty_color
@@ -378,8 +1052,8 @@ impl Dla {
         let mut attempt: usize = 0;
         // loop until we find a position that isn't full, or we hit the retry limit
         loop {
-            let randx = thread_rng().gen_range(0..self.grid.width);
-            let randy = thread_rng().gen_range(0..self.grid.height);
+            let randx = self.rng.gen_range(0..self.grid.width);
+            let randy = self.rng.gen_range(0..self.grid.height);
 
             // update idx so the loop works
             let idx = self.get_idx(randx, randy);
@@ -399,7 +1073,7 @@ impl Dla {
 
             attempt += 1;
             if attempt >= MAX_RETRIES {
-                println!("Couldn't generate a random location in {} tries! The grid must be nearly full - marking simulation as complete", MAX_RETRIES);
+                eprintln!("Couldn't generate a random location in {} tries! The grid must be nearly full - marking simulation as complete", MAX_RETRIES);
                 self.is_complete = true;
                 return (randx, randy); // this point is already filled in, or didn't meet the radius criteria
             }
@@ -411,7 +1085,6 @@ impl Dla {
     }
 
     /// Return avg updates/sec
-    #[allow(dead_code)]
     pub fn benchmark() {
         println!("{}", "Running benchmark...".to_string().bold().yellow());
         let iterations = 10;
@@ -480,10 +1153,63 @@ impl Dla {
         self.paused
     }
 
+    pub fn particles(&self) -> usize {
+        self.particles
+    }
+
+    pub fn particle_color(&self) -> ColorName {
+        get_color_name(self.fill_color)
+    }
+
+    pub fn background_color(&self) -> ColorName {
+        get_color_name(self.empty_color)
+    }
+
+    pub fn theme(&self) -> Option<Theme> {
+        self.theme
+    }
+
+    pub fn spawn_radius(&self) -> Option<usize> {
+        self.spawn_radius
+    }
+
+    pub fn noise_reduction(&self) -> Option<u32> {
+        self.noise_reduction
+    }
+
+    pub fn temperature(&self) -> Option<f64> {
+        self.temperature
+    }
+
+    pub fn max_walk(&self) -> Option<u64> {
+        self.max_walk
+    }
+
+    pub fn evaporation(&self) -> Option<f64> {
+        self.evaporation
+    }
+
+    pub fn updates(&self) -> u64 {
+        self.updates
+    }
+
     pub fn stuck_particles(&self) -> usize {
         self.stuck_particles
     }
 
+    pub fn evaporated(&self) -> usize {
+        self.evaporated
+    }
+
+    /// The id of the most recently stuck particle, which is the end of the replay timeline
+    pub fn last_id(&self) -> usize {
+        self.last_id
+    }
+
+    pub fn timeline(&self) -> Option<usize> {
+        self.timeline
+    }
+
     pub fn complete(&self) -> bool {
         self.is_complete
     }
@@ -492,6 +1218,64 @@ impl Dla {
         self.grid_type
     }
 
+    /// The settings this simulation runs with, as a config that grows it again
+    pub(crate) fn config(&self) -> DlaConfig {
+        let (width, height) = self.size();
+        DlaConfig {
+            width: width as u32,
+            height: Some(height as u32),
+            particles: self.particles,
+            grid_type: Some(format!("{:?}", self.grid_type).to_ascii_lowercase()),
+            // a color turns the theme off in Dla::from, so it's only set when there's no theme
+            color: self
+                .theme
+                .is_none()
+                .then(|| self.particle_color().to_string()),
+            background_color: Some(self.background_color().to_string()),
+            noise_reduction: self.noise_reduction,
+            temperature: self.temperature,
+            max_walk: self.max_walk,
+            evaporation: self.evaporation,
+            stickiness: self.stickiness,
+            spawn_radius: self.spawn_radius,
+            seed: Some(self.seed),
+            ..DlaConfig::default()
+        }
+    }
+
+    /// Settings saved with the grid in grid files
+    pub(crate) fn parameters(&self) -> Parameters {
+        Parameters {
+            software: Some(SOFTWARE.to_string()),
+            grid_type: Some(format!("{:?}", self.grid_type)),
+            particles: Some(self.particles),
+            spawn_radius: self.spawn_radius,
+            seed: Some(self.seed),
+            color: Some(self.particle_color().to_string()),
+            background_color: Some(self.background_color().to_string()),
+            theme: self.theme.map(|t| t.to_string()),
+            config: Some(self.config().to_args()),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn mass_radius(&self) -> &MassRadius {
+        &self.mass_radius
+    }
+
+    /// Which seed each particle grew from
+    pub fn ownership(&self) -> &Ownership {
+        &self.ownership
+    }
+
+    /// Build the aggregation tree of the grid from the parents recorded as particles stuck
+    pub fn aggregation_tree(&self) -> AggregationTree {
+        AggregationTree::from_grid(&self.grid, Some(&self.parents))
+    }
+
     pub fn size(&self) -> (usize, usize) {
         (self.grid.width, self.grid.height)
     }
@@ -501,6 +1285,10 @@ impl Dla {
         self.swap_particle_color(new_color);
     }
 
+    pub fn handle_color_by_seed_changed(&mut self, color_by_seed: bool) {
+        self.color_by_seed = color_by_seed;
+    }
+
     pub fn handle_theme_changed(&mut self, new_theme: Option<Theme>) {
         self.theme = new_theme;
     }
@@ -548,16 +1336,40 @@ impl Dla {
             panic!("Application state is out of sync with GUI! (start/stop button)");
         }
         self.paused = !self.paused;
+        if self.paused {
+            // a good time to make sure the log on disk is up to date
+            self.flush_event_log();
+        }
     }
 
     pub fn handle_save_button_clicked(&mut self, save_file: &str) {
+        // overwrite handling. Portable formats aren't compressed
+        let written = if Format::from_path(save_file).is_some() {
+            save_file.to_string()
+        } else {
+            save_file.to_string() + ".gz"
+        };
+        if std::path::Path::new(written.as_str()).exists() {
+            eprintln!("Save Button: File already exists! Ignoring to avoid overwrite.");
+            return;
+        }
+        self.grid.to_file(save_file, &self.parameters());
+    }
+
+    pub fn handle_save_image_clicked(&mut self, image_file: &str, scale: usize) {
         // overwrite handling
-        let save_file_gz = save_file.to_string() + ".gz";
-        if std::path::Path::new(save_file_gz.as_str()).exists() {
-            println!("Save Button: File already exists! Ignoring to avoid overwrite.");
+        if std::path::Path::new(image_file).exists() {
+            eprintln!("Save Image Button: File already exists! Ignoring to avoid overwrite.");
             return;
         }
-        self.grid.to_file(save_file);
+        self.save_image(image_file, scale);
+    }
+
+    pub fn handle_export_rg_clicked(&self, file: &str) {
+        match self.mass_radius.to_csv(file) {
+            Ok(()) => eprintln!("Wrote radius of gyration series to file: {}", file),
+            Err(err) => eprintln!("Error encountered writing {}: {:?}", file, err),
+        }
     }
 
     pub fn handle_from_button_clicked(&mut self, from_file: &str) {
@@ -565,18 +1377,11 @@ impl Dla {
         let old_size = self.grid.width;
         let maybe_grid = Grid::from_file(from_file);
         if let Some(grid) = maybe_grid {
-            self.grid = grid;
+            self.set_grid(grid);
         } else {
             return; // couldn't read grid in from file. whatever
         }
 
-        // count the stuck particles in the grid we read in
-        self.stuck_particles = self.grid.cells.iter().filter(|&n| n.filled).count();
-
-        // particles: same as stuck particles since we're marking as complete
-        self.particles = self.stuck_particles;
-        self.is_complete = true;
-
         if old_size != self.grid.width {
             // we need to resize everything
             self.do_resize = true;
@@ -586,10 +1391,71 @@ impl Dla {
         // time it will get the updated value from the backend
     }
 
+    /// Replace the grid with one that's already grown, and mark the simulation complete
+    fn set_grid(&mut self, grid: Grid) {
+        self.grid = grid;
+        self.hits = vec![0; self.grid.cells.len()];
+
+        // count the stuck particles in the grid we read in
+        self.stuck_particles = self.grid.cells.iter().filter(|&n| n.filled).count();
+
+        // particles with an id stuck during the simulation that made the grid. Those are free to relax
+        self.mobile = (0..self.grid.cells.len())
+            .filter(|&idx| self.grid.filled(idx) && self.grid.cells[idx].id != 0)
+            .collect();
+        self.last_id = self.grid.cells.iter().map(|c| c.id).max().unwrap_or(0);
+        self.timeline = None;
+        self.mass_radius = MassRadius::from_grid(&self.grid);
+        // we don't know how the grid actually grew, so infer the tree from it
+        self.parents.clear();
+        self.children.clear();
+        for idx in self.mobile.clone() {
+            self.set_parent(self.grid.cells[idx].id, tree::parent_of(&self.grid, idx));
+        }
+        self.ownership = Ownership::from_grid(&self.grid);
+        // whatever was logged belongs to the grid that was replaced
+        self.restart_event_log();
+
+        // particles: same as stuck particles since we're marking as complete
+        self.particles = self.stuck_particles;
+        self.is_complete = true;
+    }
+
     pub fn handle_spawn_radius_changed(&mut self, new_radius: Option<usize>) {
         self.spawn_radius = new_radius;
     }
 
+    pub fn handle_noise_reduction_changed(&mut self, new_threshold: Option<u32>) {
+        self.noise_reduction = new_threshold;
+    }
+
+    pub fn handle_temperature_changed(&mut self, new_temperature: Option<f64>) {
+        self.temperature = new_temperature;
+    }
+
+    pub fn handle_max_walk_changed(&mut self, new_max_walk: Option<u64>) {
+        self.max_walk = new_max_walk;
+    }
+
+    pub fn handle_evaporation_changed(&mut self, new_evaporation: Option<f64>) {
+        self.evaporation = new_evaporation;
+    }
+
+    /// Start logging stuck particles to the file at `path`, or stop logging if it's None
+    pub fn handle_event_log_changed(&mut self, path: Option<&str>) {
+        self.flush_event_log();
+        self.event_log = path.and_then(Self::open_event_log);
+    }
+
+    pub fn event_logging(&self) -> bool {
+        self.event_log.is_some()
+    }
+
+    /// Only draw particles with an id <= `last_visible`, or everything if it's None
+    pub fn handle_timeline_changed(&mut self, last_visible: Option<usize>) {
+        self.timeline = last_visible;
+    }
+
     /// A reset is just a grid type swap with a grid of the same type
     pub fn handle_reset(&mut self, width: u32, height: u32) {
         let height_option = if width != self.grid.width as u32 || height != self.grid.height as u32
@@ -604,3 +1470,114 @@ impl Dla {
         self.swap_grid_type(self.grid_type, height_option);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Grow a small seeded aggregate with `config`'s settings
+    fn sim(config: DlaConfig) -> Dla {
+        Dla::from(&DlaConfig {
+            width: 40,
+            particles: 200,
+            seed: Some(3),
+            ..config
+        })
+    }
+
+    /// Check that the bookkeeping of stuck particles matches the grid
+    fn assert_consistent(sim: &Dla) {
+        let mut stuck: Vec<usize> = (0..sim.grid.cells.len())
+            .filter(|&idx| sim.grid.filled(idx) && sim.grid.cells[idx].id != 0)
+            .collect();
+        stuck.sort_unstable();
+        let mut mobile = sim.mobile.clone();
+        mobile.sort_unstable();
+        assert_eq!(mobile, stuck);
+
+        // the walker fills its cell too
+        let filled = sim.grid.stuck_particles();
+        assert_eq!(sim.stuck_particles, filled - sim.cur_part.exists as usize);
+
+        let ids: Vec<usize> = stuck.iter().map(|&idx| sim.grid.cells[idx].id).collect();
+        let mut children = vec![0; sim.children.len()];
+        for (id, parent) in sim.parents.iter().enumerate() {
+            match parent {
+                // every particle on the grid hangs from an older one that's still there
+                Some(parent) => {
+                    assert!(ids.contains(&id), "particle {} isn't on the grid", id);
+                    assert!(*parent < id);
+                    assert!(*parent == 0 || ids.contains(parent));
+                    children[*parent] += 1;
+                }
+                None => assert!(!ids.contains(&id), "particle {} has no parent", id),
+            }
+        }
+        assert_eq!(children, sim.children);
+    }
+
+    #[test]
+    fn relaxation_keeps_the_tree() {
+        let mut sim = sim(DlaConfig {
+            temperature: Some(2.0),
+            ..Default::default()
+        });
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+        let mut moves = 0;
+        while !sim.complete() {
+            sim.update();
+            assert_consistent(&sim);
+            for &idx in sim.mobile.iter() {
+                let id = sim.grid.cells[idx].id;
+                if positions.insert(id, idx).is_some_and(|old| old != idx) {
+                    moves += 1;
+                }
+            }
+        }
+        assert!(moves > 0, "nothing relaxed");
+    }
+
+    #[test]
+    fn noise_reduction_needs_hits() {
+        let mut sim = sim(DlaConfig {
+            noise_reduction: Some(3),
+            ..Default::default()
+        });
+        sim.run();
+        // every particle's site was touched at least 3 times before it filled
+        assert!(!sim.mobile.is_empty());
+        for &idx in sim.mobile.iter() {
+            assert!(
+                sim.hits[idx] >= 3,
+                "site {} filled after {} hits",
+                idx,
+                sim.hits[idx]
+            );
+        }
+    }
+
+    #[test]
+    fn walkers_expire() {
+        let mut sim = sim(DlaConfig {
+            max_walk: Some(5),
+            ..Default::default()
+        });
+        for _ in 0..5000 {
+            sim.update();
+            assert!(sim.cur_part.steps <= 5);
+        }
+        assert!(sim.evaporated > 0);
+
+        let mut sim = self::sim(DlaConfig {
+            evaporation: Some(0.5),
+            ..Default::default()
+        });
+        for _ in 0..5000 {
+            sim.update();
+        }
+        // about half of the walkers that take a step evaporate on it
+        assert!(sim.evaporated > 1000, "{} evaporated", sim.evaporated);
+        assert_consistent(&sim);
+    }
+}
@@ -0,0 +1,270 @@
+//! Many independent simulations with the same settings, for statistics over an ensemble of aggregates
+//!
+//! A single aggregate is noisy, so measurements like the fractal dimension are averaged over many runs. Every run uses
+//! the same config with its own seed: run i uses base seed + i, so any run can be grown again on its own with --seed.
+use colored::Colorize;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::analysis;
+use crate::config::DlaConfig;
+use crate::dla::Dla;
+
+/// Measurements of one finished run
+#[derive(Clone, Debug)]
+pub struct RunMetrics {
+    pub run: usize,
+    pub seed: u64,
+    /// Particles that stuck, not counting seeds
+    pub particles: usize,
+    /// Box-counting dimension of the final grid
+    pub box_dimension: Option<f64>,
+    /// Dimension from the radius of gyration scaling, 1 / v where Rg ~ N^v
+    pub rg_dimension: Option<f64>,
+    pub radius_of_gyration: f64,
+    pub max_radius: usize,
+    /// Updates (walker steps and spawns) per particle that stuck
+    pub steps_per_particle: f64,
+    /// Walkers removed by max walk or evaporation
+    pub evaporated: usize,
+    /// Seconds the run took
+    pub wall_time: f64,
+}
+
+/// Names of the numeric metrics, in the order RunMetrics::values returns them
+const METRICS: [&str; 8] = [
+    "particles",
+    "box_dimension",
+    "rg_dimension",
+    "radius_of_gyration",
+    "max_radius",
+    "steps_per_particle",
+    "evaporated",
+    "wall_time",
+];
+
+impl RunMetrics {
+    fn values(&self) -> [Option<f64>; 8] {
+        [
+            Some(self.particles as f64),
+            self.box_dimension,
+            self.rg_dimension,
+            Some(self.radius_of_gyration),
+            Some(self.max_radius as f64),
+            Some(self.steps_per_particle),
+            Some(self.evaporated as f64),
+            Some(self.wall_time),
+        ]
+    }
+}
+
+/// Mean and standard deviation of one metric over the runs that have it
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub metric: &'static str,
+    /// Number of runs with a value for this metric
+    pub runs: usize,
+    pub mean: f64,
+    /// Sample standard deviation. 0 if there's only 1 run
+    pub std_dev: f64,
+}
+
+/// Grow `runs` aggregates with `config`, spread over `threads` threads. Run i is seeded with base_seed + i.
+///
+/// Event logs, output files, images, animations and frame streams in the config are ignored, since every run would
+/// write to the same file. Returns the metrics of every run, in run order.
+pub fn run_ensemble(
+    config: &DlaConfig,
+    runs: usize,
+    threads: usize,
+    base_seed: u64,
+) -> Vec<RunMetrics> {
+    let mut config = config.clone();
+    config.event_log = None;
+    config.to_file = None;
+    config.image = None;
+    config.animation = None;
+    config.stream = None;
+
+    let next_run = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let metrics = Mutex::new(Vec::with_capacity(runs));
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, runs.max(1)) {
+            scope.spawn(|| loop {
+                let run = next_run.fetch_add(1, Ordering::SeqCst);
+                if run >= runs {
+                    break;
+                }
+                let result = measure_run(&config, run, base_seed.wrapping_add(run as u64));
+                metrics.lock().unwrap().push(result);
+
+                let done = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                eprint!(
+                    "\r{}{}{}{}",
+                    "Finished run: ".to_string().bold().blue(),
+                    done.to_string().green(),
+                    " of ".to_string().bold().blue(),
+                    runs.to_string().green()
+                );
+                std::io::stderr().flush().unwrap();
+            });
+        }
+    });
+    eprintln!();
+
+    let mut metrics = metrics.into_inner().unwrap();
+    metrics.sort_by_key(|m| m.run);
+    metrics
+}
+
+fn measure_run(config: &DlaConfig, run: usize, seed: u64) -> RunMetrics {
+    let mut config = config.clone();
+    config.seed = Some(seed);
+
+    let now = Instant::now();
+    let mut sim = Dla::from(&config);
+    sim.run();
+    let wall_time = now.elapsed().as_secs_f64();
+
+    let grid = sim.grid();
+    let mass_radius = sim.mass_radius();
+    let particles = mass_radius.count();
+    RunMetrics {
+        run,
+        seed,
+        particles,
+        box_dimension: analysis::box_counting(grid, &analysis::default_box_sizes(grid)).dimension(),
+        rg_dimension: mass_radius.fit().map(|fit| 1.0 / fit.slope),
+        radius_of_gyration: mass_radius.radius_of_gyration(),
+        max_radius: mass_radius.max_radius(),
+        steps_per_particle: sim.updates() as f64 / particles.max(1) as f64,
+        evaporated: sim.evaporated(),
+        wall_time,
+    }
+}
+
+/// Mean and standard deviation of every metric
+pub fn summarize(metrics: &[RunMetrics]) -> Vec<Summary> {
+    let values: Vec<[Option<f64>; 8]> = metrics.iter().map(|m| m.values()).collect();
+    METRICS
+        .iter()
+        .enumerate()
+        .map(|(i, metric)| {
+            let column: Vec<f64> = values.iter().filter_map(|v| v[i]).collect();
+            let n = column.len() as f64;
+            let mean = if column.is_empty() {
+                0.0
+            } else {
+                column.iter().sum::<f64>() / n
+            };
+            let std_dev = if column.len() < 2 {
+                0.0
+            } else {
+                (column.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+            };
+            Summary {
+                metric,
+                runs: column.len(),
+                mean,
+                std_dev,
+            }
+        })
+        .collect()
+}
+
+/// Write one row per run. Metrics a run doesn't have are left empty
+pub fn to_csv(metrics: &[RunMetrics], file_name: &str) -> std::io::Result<()> {
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    writeln!(writer, "run,seed,{}", METRICS.join(","))?;
+    for m in metrics.iter() {
+        let values: Vec<String> = m
+            .values()
+            .iter()
+            .map(|v| v.map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+        writeln!(writer, "{},{},{}", m.run, m.seed, values.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Write one row per metric with its mean and standard deviation
+pub fn summary_to_csv(summaries: &[Summary], file_name: &str) -> std::io::Result<()> {
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    writeln!(writer, "metric,runs,mean,std_dev")?;
+    for s in summaries.iter() {
+        writeln!(writer, "{},{},{},{}", s.metric, s.runs, s.mean, s.std_dev)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(particles: usize, box_dimension: Option<f64>) -> RunMetrics {
+        RunMetrics {
+            run: 0,
+            seed: 0,
+            particles,
+            box_dimension,
+            rg_dimension: None,
+            radius_of_gyration: 0.0,
+            max_radius: 0,
+            steps_per_particle: 0.0,
+            evaporated: 0,
+            wall_time: 0.0,
+        }
+    }
+
+    #[test]
+    fn seeded_runs_repeat() {
+        let config = DlaConfig {
+            width: 40,
+            particles: 30,
+            ..Default::default()
+        };
+        let first = run_ensemble(&config, 4, 2, 11);
+        let second = run_ensemble(&config, 4, 3, 11);
+        assert_eq!(first.len(), 4);
+        for (a, b) in first.iter().zip(second.iter()) {
+            assert_eq!(a.run, b.run);
+            assert_eq!(a.seed, 11 + a.run as u64);
+            // everything but the wall time
+            assert_eq!(a.values()[..7], b.values()[..7]);
+        }
+    }
+
+    #[test]
+    fn summary_stats() {
+        let runs = [
+            metrics(2, Some(1.0)),
+            metrics(4, None),
+            metrics(4, Some(2.0)),
+            metrics(4, None),
+            metrics(5, None),
+            metrics(5, None),
+            metrics(7, None),
+            metrics(9, None),
+        ];
+        let summaries = summarize(&runs);
+        assert_eq!(summaries[0].metric, "particles");
+        assert_eq!(summaries[0].runs, 8);
+        assert_eq!(summaries[0].mean, 5.0);
+        // sum of squared deviations is 32, over n - 1 = 7
+        assert!((summaries[0].std_dev - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+
+        // runs without a value don't count
+        assert_eq!(summaries[1].runs, 2);
+        assert_eq!(summaries[1].mean, 1.5);
+        assert!((summaries[1].std_dev - 0.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(summaries[2].runs, 0);
+        assert_eq!(summaries[2].std_dev, 0.0);
+
+        // a single run has no spread
+        assert_eq!(summarize(&runs[..1])[0].std_dev, 0.0);
+    }
+}
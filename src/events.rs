@@ -0,0 +1,218 @@
+//! Append-only log of particles sticking during a simulation
+//!
+//! Every time a walker sticks, an event is streamed to disk. The log records the growth of the whole run so it can be
+//! analyzed or replayed later without rerunning the simulation. When the simulation is reset the log starts over, so it
+//! only ever holds one run.
+//!
+//! Only sticking is logged. With surface relaxation on (a temperature is set), particles hop or detach after they've
+//! stuck, and those moves aren't in the log, so replaying it won't give the final grid.
+//!
+//! Two formats are supported, picked by the file extension:
+//! - `.csv`: a header row followed by one row per event
+//! - anything else: a compact binary format. The file starts with the 8 byte magic `RDLAEV01`, followed by one
+//!   40 byte little-endian record per event: id (u64), x (u32), y (u32), update (u64), steps (u64),
+//!   neighbor x (u32), neighbor y (u32)
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Magic number at the start of binary event logs. The last 2 characters are the format version
+pub const BINARY_MAGIC: &[u8; 8] = b"RDLAEV01";
+
+const CSV_HEADER: &str = "id,x,y,update,steps,neighbor_x,neighbor_y";
+
+/// A particle sticking to the aggregate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StickEvent {
+    /// The id the particle was given when it stuck. These increase in the order particles stick
+    pub id: usize,
+    /// Position (x, y) where the particle stuck
+    pub pos: (usize, usize),
+    /// The simulation's update count when the particle stuck
+    pub update: u64,
+    /// How many steps the walker took between spawning and sticking
+    pub steps: u64,
+    /// Position (x, y) of the filled neighbor the walker attached to. This is its parent in the aggregation tree, see
+    /// tree::parent_of
+    pub neighbor: (usize, usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogFormat {
+    Csv,
+    Binary,
+}
+
+#[derive(Debug)]
+pub struct EventLog {
+    writer: BufWriter<File>,
+    format: LogFormat,
+    /// Where the log is being written, for error messages
+    path: String,
+}
+
+impl EventLog {
+    /// Create a new log at `path`, overwriting anything that's there. The format is picked from the extension
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        let format = if path.to_ascii_lowercase().ends_with(".csv") {
+            LogFormat::Csv
+        } else {
+            LogFormat::Binary
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            LogFormat::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+            LogFormat::Binary => writer.write_all(BINARY_MAGIC)?,
+        }
+
+        Ok(Self {
+            writer,
+            format,
+            path: path.to_string(),
+        })
+    }
+
+    /// Start the log over for a new run, dropping everything recorded so far
+    pub fn restart(&mut self) -> std::io::Result<()> {
+        // anything still buffered belongs to the old run, and would land in the new file after it's truncated
+        self.writer.flush()?;
+        *self = Self::create(&self.path)?;
+        Ok(())
+    }
+
+    /// Append an event to the log
+    pub fn record(&mut self, event: &StickEvent) -> std::io::Result<()> {
+        match self.format {
+            LogFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{},{},{},{}",
+                event.id,
+                event.pos.0,
+                event.pos.1,
+                event.update,
+                event.steps,
+                event.neighbor.0,
+                event.neighbor.1
+            ),
+            LogFormat::Binary => {
+                let w = &mut self.writer;
+                w.write_u64::<LittleEndian>(event.id as u64)?;
+                w.write_u32::<LittleEndian>(event.pos.0 as u32)?;
+                w.write_u32::<LittleEndian>(event.pos.1 as u32)?;
+                w.write_u64::<LittleEndian>(event.update)?;
+                w.write_u64::<LittleEndian>(event.steps)?;
+                w.write_u32::<LittleEndian>(event.neighbor.0 as u32)?;
+                w.write_u32::<LittleEndian>(event.neighbor.1 as u32)
+            }
+        }
+    }
+
+    /// Make sure everything recorded so far is on disk
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Read every event in the log at `path`. The format is picked from the extension, like EventLog::create
+pub fn read(path: &str) -> std::io::Result<Vec<StickEvent>> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut events = Vec::new();
+    if path.to_ascii_lowercase().ends_with(".csv") {
+        let text = std::fs::read_to_string(path)?;
+        let lines = text.lines().count();
+        for (line_number, line) in text.lines().enumerate().skip(1) {
+            let fields: Option<Vec<u64>> = line.split(',').map(|f| f.trim().parse().ok()).collect();
+            match fields.as_deref() {
+                Some(&[id, x, y, update, steps, neighbor_x, neighbor_y]) => {
+                    events.push(StickEvent {
+                        id: id as usize,
+                        pos: (x as usize, y as usize),
+                        update,
+                        steps,
+                        neighbor: (neighbor_x as usize, neighbor_y as usize),
+                    })
+                }
+                // a run that was cut off can leave half a line at the end
+                _ if line_number + 1 == lines => break,
+                _ => return Err(invalid(format!("bad event on line {}", line_number + 1))),
+            }
+        }
+        return Ok(events);
+    }
+
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(BINARY_MAGIC) {
+        return Err(invalid("not an event log".to_string()));
+    }
+    // any partial record at the end is dropped
+    for mut record in bytes[BINARY_MAGIC.len()..].chunks_exact(40) {
+        let r = &mut record;
+        events.push(StickEvent {
+            id: r.read_u64::<LittleEndian>()? as usize,
+            pos: (
+                r.read_u32::<LittleEndian>()? as usize,
+                r.read_u32::<LittleEndian>()? as usize,
+            ),
+            update: r.read_u64::<LittleEndian>()?,
+            steps: r.read_u64::<LittleEndian>()?,
+            neighbor: (
+                r.read_u32::<LittleEndian>()? as usize,
+                r.read_u32::<LittleEndian>()? as usize,
+            ),
+        });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<StickEvent> {
+        (1..=4)
+            .map(|id| StickEvent {
+                id,
+                pos: (id * 3, 70_000 + id),
+                update: 1 << (30 + id),
+                steps: id as u64 * 100,
+                neighbor: (id * 3 - 1, 70_000 + id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for extension in ["csv", "bin"] {
+            let path = std::env::temp_dir()
+                .join(format!("dla-events-{}.{}", std::process::id(), extension))
+                .to_string_lossy()
+                .to_string();
+            let mut log = EventLog::create(&path).unwrap();
+            log.record(&events()[0]).unwrap();
+            // a reset drops the first run
+            log.restart().unwrap();
+            for event in events().iter() {
+                log.record(event).unwrap();
+            }
+            log.flush().unwrap();
+            assert_eq!(read(&path).unwrap(), events());
+
+            // a run cut off partway through an event loses only that event
+            let mut bytes = std::fs::read(&path).unwrap();
+            let partial = if extension == "csv" {
+                &b"5,15,7"[..]
+            } else {
+                &[5, 0, 0, 0, 0][..]
+            };
+            bytes.extend_from_slice(partial);
+            std::fs::write(&path, bytes).unwrap();
+            assert_eq!(read(&path).unwrap(), events());
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
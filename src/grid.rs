@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::{fs, path::Path, process::Command};
 
 use crate::config::GridType;
+use crate::gridfile::{self, GridFile, Parameters};
+use crate::image;
+use crate::portable::{self, Format};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Particle {
@@ -31,11 +34,26 @@ impl Grid {
         self.cells[idx].filled = filled;
     }
 
-    /// Serialize the grid to a file
-    pub fn to_file(&self, file_name: &str) {
-        println!("Writing grid out to file: {}", file_name);
+    /// Write the grid and the settings that grew it to a file, in the format described in gridfile. Files ending in
+    /// .pbm, .pgm or .txt are written in that format instead (see portable), without the settings, and aren't
+    /// compressed. PNGs are refused: they're rendered images, which only the simulation can draw
+    pub fn to_file(&self, file_name: &str, parameters: &Parameters) {
+        if file_name.to_ascii_lowercase().ends_with(".png") {
+            eprintln!(
+                "Not writing grid to {}: PNGs are images, save them with --image or the Save Image button",
+                file_name
+            );
+            return;
+        }
+        eprintln!("Writing grid out to file: {}", file_name);
+        if let Some(format) = Format::from_path(file_name) {
+            if let Err(err) = portable::write(file_name, self, format) {
+                eprintln!("Error encountered writing grid to file: {:?}", err);
+            }
+            return;
+        }
         // serialize
-        let serialized = bincode::serialize(&self).unwrap();
+        let serialized = gridfile::encode(self, parameters);
 
         // this is where we'll put it
         let file_path: &Path = Path::new(file_name);
@@ -44,7 +62,7 @@ impl Grid {
         let write_result = fs::write(file_path, serialized);
 
         if write_result.is_err() {
-            println!("Error encountered writing grid to file: {:?}", write_result);
+            eprintln!("Error encountered writing grid to file: {:?}", write_result);
         }
 
         // zip it
@@ -52,15 +70,31 @@ impl Grid {
     }
 
     /// Read in a grid from this file
-    pub fn from_file(mut file_name: &str) -> Option<Self> {
-        println!("Reading grid in from file: {}", file_name);
+    pub fn from_file(file_name: &str) -> Option<Self> {
+        Self::read_file(file_name).map(|file| file.grid)
+    }
+
+    /// Read in a grid from this file, along with the format version and settings that grew it when the file has them
+    pub fn read_file(mut file_name: &str) -> Option<GridFile> {
+        // on stderr, so commands that print their report to stdout can be redirected to a file
+        eprintln!("Reading grid in from file: {}", file_name);
+        if file_name.to_ascii_lowercase().ends_with(".png") {
+            return image::grid_from_png(file_name);
+        }
+        if let Some(format) = Format::from_path(file_name) {
+            return portable::read(file_name, format).map(|grid| GridFile {
+                grid,
+                version: None,
+                parameters: Parameters::default(),
+            });
+        }
         // catch stupid mistakes for now
         let mut recompress: bool = false;
         if file_name.ends_with(".gz") {
             let output = Self::decompress(file_name);
 
             if !output.stderr.is_empty() {
-                println!(
+                eprintln!(
                     "Error encountered decompressing file {}: {:?}",
                     file_name,
                     String::from_utf8(output.stderr).unwrap()
@@ -77,7 +111,7 @@ impl Grid {
         // pull the blob in from file
         let read_result = fs::read(file_path);
         if read_result.is_err() {
-            println!(
+            eprintln!(
                 "Error encountered reading from filesystem: {:?}",
                 read_result
             );
@@ -92,8 +126,14 @@ impl Grid {
                 .expect("Failed to remove uncompressed input file");
         }
 
-        // deserialize it and return it!
-        Some(bincode::deserialize(&serialized).unwrap())
+        // decode it and return it!
+        match gridfile::decode(&serialized) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                eprintln!("Error encountered reading grid from {}: {}", file_name, err);
+                None
+            }
+        }
     }
 
     #[allow(dead_code)]
@@ -123,13 +163,18 @@ impl Grid {
     }
 
     pub fn from(grid_type: GridType, width: u32, height: u32) -> Self {
+        Self::from_rng(grid_type, width, height, &mut thread_rng())
+    }
+
+    /// Same as from, but grid types with random seeds place them with `rng`
+    pub fn from_rng(grid_type: GridType, width: u32, height: u32, rng: &mut impl Rng) -> Self {
         let width = width as usize;
         let height = height as usize;
         let cells = match grid_type {
             GridType::BottomEdge => Self::cells_bottom_edge(width, height),
             GridType::AllEdges => Self::cells_all_edges(width, height),
             GridType::FourDots => Self::cells_four_dots(width, height),
-            GridType::RandFive => Self::cells_random5(width, height),
+            GridType::RandFive => Self::cells_random5(width, height, rng),
             GridType::Circle => {
                 Self::cells_circle(width, height, std::cmp::min(width, height) / 10)
             }
@@ -233,12 +278,12 @@ impl Grid {
         new
     }
 
-    fn cells_random5(width: usize, height: usize) -> Vec<bool> {
+    fn cells_random5(width: usize, height: usize, rng: &mut impl Rng) -> Vec<bool> {
         let mut new = Self::cells_empty(width, height);
 
         for _ in 0..5 {
-            let randx = thread_rng().gen_range(0..width);
-            let randy = thread_rng().gen_range(0..height);
+            let randx = rng.gen_range(0..width);
+            let randy = rng.gen_range(0..height);
 
             let idx = Self::get_idx(width, randx, randy);
             new[idx] = true;
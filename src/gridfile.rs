@@ -0,0 +1,235 @@
+//! The grid file format: a versioned container with the grid and the settings that grew it
+//!
+//! Grid files are gzipped. Inside, everything is little-endian:
+//! - the 8 byte magic `RDLAGRID`
+//! - format version (u32), currently 1
+//! - width and height (u32 each)
+//! - length of the parameters (u32), then the parameters as JSON: grid type, particles, spawn radius, seed, colors,
+//!   theme and the flags that grow the grid again. Every field is optional, so readers ignore ones they don't know
+//! - the cells (u64 each) row by row, from the top left. Empty cells are 0 and particles are their id + 1
+//!
+//! Files from before the container are a bare bincode dump of Grid. They don't start with the magic, and are still read
+//! by the legacy reader.
+use serde::{Deserialize, Serialize};
+
+use crate::grid::{Grid, Particle};
+
+/// Magic number at the start of grid files
+pub const MAGIC: &[u8; 8] = b"RDLAGRID";
+
+/// The format version written. Files with a later version are refused rather than misread
+pub const VERSION: u32 = 1;
+
+/// Settings of the run that grew a grid. Missing when they weren't known, like for grids converted from an image
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Parameters {
+    /// Name and version of the program that wrote the file
+    pub software: Option<String>,
+    pub grid_type: Option<String>,
+    /// Particles the run was set to grow
+    pub particles: Option<usize>,
+    pub spawn_radius: Option<usize>,
+    pub seed: Option<u64>,
+    pub color: Option<String>,
+    pub background_color: Option<String>,
+    pub theme: Option<String>,
+    /// Command line flags that grow the grid again
+    pub config: Option<String>,
+}
+
+/// A grid read from a file
+#[derive(Clone, Debug)]
+pub struct GridFile {
+    pub grid: Grid,
+    /// The format version, or None for a legacy file
+    pub version: Option<u32>,
+    pub parameters: Parameters,
+}
+
+/// The file's contents, before they're compressed
+pub fn encode(grid: &Grid, parameters: &Parameters) -> Vec<u8> {
+    let json = serde_json::to_vec(parameters).expect("Grid parameters should serialize");
+    let mut bytes = Vec::with_capacity(24 + json.len() + grid.cells.len() * 8);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend((grid.width as u32).to_le_bytes());
+    bytes.extend((grid.height as u32).to_le_bytes());
+    bytes.extend((json.len() as u32).to_le_bytes());
+    bytes.extend(json);
+    for cell in grid.cells.iter() {
+        let value = if cell.filled { cell.id as u64 + 1 } else { 0 };
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes
+}
+
+/// Read a file's decompressed contents, in either the container or the legacy format
+pub fn decode(bytes: &[u8]) -> Result<GridFile, String> {
+    if !bytes.starts_with(MAGIC) {
+        return legacy(bytes);
+    }
+
+    let mut pos = MAGIC.len();
+    let mut next_u32 = || -> Result<u32, String> {
+        let value = bytes
+            .get(pos..pos + 4)
+            .ok_or("the file is cut off in its header")?;
+        pos += 4;
+        Ok(u32::from_le_bytes(value.try_into().unwrap()))
+    };
+    let version = next_u32()?;
+    if version > VERSION {
+        return Err(format!(
+            "the file is format version {}, this build reads up to version {}",
+            version, VERSION
+        ));
+    }
+    let width = next_u32()? as usize;
+    let height = next_u32()? as usize;
+    let json_len = next_u32()? as usize;
+
+    let json = bytes
+        .get(pos..pos + json_len)
+        .ok_or("the file is cut off in its parameters")?;
+    let parameters: Parameters =
+        serde_json::from_slice(json).map_err(|err| format!("bad parameters: {}", err))?;
+    let payload = &bytes[pos + json_len..];
+    let cell_count = width.checked_mul(height).ok_or("bad grid size")?;
+    let expected = cell_count.checked_mul(8).ok_or("bad grid size")?;
+    if payload.len() != expected {
+        return Err(format!(
+            "expected {} cells for a {}x{} grid, found {} bytes of them",
+            cell_count,
+            width,
+            height,
+            payload.len()
+        ));
+    }
+    let cells = payload
+        .chunks_exact(8)
+        .map(|value| {
+            let value = u64::from_le_bytes(value.try_into().unwrap());
+            Particle {
+                filled: value != 0,
+                id: value.saturating_sub(1) as usize,
+            }
+        })
+        .collect();
+
+    Ok(GridFile {
+        grid: Grid {
+            cells,
+            width,
+            height,
+        },
+        version: Some(version),
+        parameters,
+    })
+}
+
+/// Files written before the container, a bincode dump of Grid
+fn legacy(bytes: &[u8]) -> Result<GridFile, String> {
+    let grid: Grid = bincode::deserialize(bytes)
+        .map_err(|err| format!("not a grid file, or a damaged one: {}", err))?;
+    if grid.cells.len() != grid.width * grid.height {
+        return Err(format!(
+            "{} cells for a {}x{} grid",
+            grid.cells.len(),
+            grid.width,
+            grid.height
+        ));
+    }
+    Ok(GridFile {
+        grid,
+        version: None,
+        parameters: Parameters::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_grid() -> Grid {
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            12
+        ];
+        for (idx, id) in [(5, 0), (6, 1), (2, 2), (10, 3)] {
+            cells[idx] = Particle { filled: true, id };
+        }
+        Grid {
+            cells,
+            width: 4,
+            height: 3,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let grid = small_grid();
+        let parameters = Parameters {
+            software: Some("dla test".to_string()),
+            grid_type: Some("Center".to_string()),
+            particles: Some(4),
+            seed: Some(7),
+            config: Some("--width 4 --height 3 --particles 4 --seed 7".to_string()),
+            ..Parameters::default()
+        };
+        let file = decode(&encode(&grid, &parameters)).unwrap();
+        assert_eq!(file.version, Some(VERSION));
+        assert_eq!(file.parameters, parameters);
+        assert_eq!((file.grid.width, file.grid.height), (4, 3));
+        for (read, written) in file.grid.cells.iter().zip(grid.cells.iter()) {
+            assert_eq!(read.filled, written.filled);
+            if written.filled {
+                assert_eq!(read.id, written.id);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut bytes = encode(&small_grid(), &Parameters::default());
+        // a newer version is refused
+        bytes[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(decode(&bytes).unwrap_err().contains("format version"));
+
+        // without the magic it's taken for a legacy file, which it isn't
+        bytes[0] = b'X';
+        assert!(decode(&bytes).is_err());
+
+        // a size too big to hold
+        let mut bytes = encode(&small_grid(), &Parameters::default());
+        bytes[12..20].copy_from_slice(&[0xff; 8]);
+        assert_eq!(decode(&bytes).unwrap_err(), "bad grid size");
+
+        // cut off
+        let bytes = encode(&small_grid(), &Parameters::default());
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(&bytes[..14]).is_err());
+    }
+
+    #[test]
+    fn legacy_file() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/grids/g1.gz");
+        let output = std::process::Command::new("gzip")
+            .args(["-dc", path])
+            .output()
+            .expect("Failed to decompress grids/g1.gz");
+        let file = decode(&output.stdout).unwrap();
+        assert_eq!(file.version, None);
+        assert_eq!(file.parameters, Parameters::default());
+        assert_eq!((file.grid.width, file.grid.height), (1000, 1000));
+        assert_eq!(file.grid.cells.len(), 1000 * 1000);
+        assert!(file
+            .grid
+            .cells
+            .iter()
+            .any(|cell| cell.filled && cell.id > 0));
+    }
+}
@@ -7,9 +7,10 @@ use strum::IntoEnumIterator;
 use winit::event_loop::EventLoopWindowTarget;
 use winit::window::Window;
 
+use crate::analysis::{self, AngularDistribution, BoxCounting, Lacunarity, LinearFit};
 use crate::colors::{ColorName, Theme};
 use crate::config::GridType;
-use crate::dla::{DEFAULT_BACK_CLR, DEFAULT_PART_CLR, DEFAULT_THEME};
+use crate::dla::DEFAULT_THEME;
 use crate::Dla;
 
 /// Manages all state required for rendering egui over `Pixels`.
@@ -31,6 +32,19 @@ struct Gui {
     /// Only show the egui window when true.
     window_open: bool,
     about_open: bool,
+    analysis_open: bool,
+
+    /// Last box-counting result computed in the analysis window
+    box_counting: Option<BoxCounting>,
+    /// Last fit of the radius of gyration scaling computed in the analysis window
+    rg_fit: Option<LinearFit>,
+    rg_file: String,
+    /// Summary of the aggregation tree computed in the analysis window: (tips, max depth, mean depth)
+    tree_summary: Option<(usize, usize, f64)>,
+    /// Last lacunarity computed in the analysis window, at the same box sizes as box counting
+    lacunarity: Option<Lacunarity>,
+    /// Last angular mass distribution computed in the analysis window
+    angular: Option<AngularDistribution>,
 
     paused: bool,
 
@@ -47,6 +61,10 @@ struct Gui {
 
     to_file: String,
     from_file: String,
+    image_file: String,
+    image_scale: usize,
+    event_log_file: String,
+    event_logging: bool, // is the backend currently writing the event log?
 
     particle_color: ColorName,
     background_color: ColorName,
@@ -54,6 +72,18 @@ struct Gui {
     spawn_radius: usize,
     enable_spawn_radius: bool,
 
+    noise_reduction: u32,
+    enable_noise_reduction: bool,
+
+    temperature: f64,
+    enable_temperature: bool,
+
+    max_walk: u64,
+    enable_max_walk: bool,
+    evaporation: f64,
+    enable_evaporation: bool,
+    evaporated: usize, // walkers removed before they stuck
+
     // We have r/w access to the grid directly, so that we can just tell it how to update stuff in response to
     // certain gui changes
     arc: Arc<Mutex<Dla>>,
@@ -66,6 +96,15 @@ struct Gui {
 
     // is time coloring enabled?
     time_coloring: bool,
+    // color particles by the seed they grew from? Overrides the theme and particle color
+    color_by_seed: bool,
+
+    // replay of the growth timeline
+    replay: bool,         // only show particles with id <= timeline
+    timeline: usize,      // last particle id to show
+    last_id: usize,       // end of the timeline
+    replay_playing: bool, // is the timeline advancing on its own?
+    replay_speed: usize,  // particles added to the timeline per frame while playing
 
     theme: Theme,
 }
@@ -77,6 +116,9 @@ pub const UNPAUSE_BUTTON_TEXT: &str = "Start";
 const RESET_BUTTON_TEXT: &str = "Reset";
 const SAVE_BUTTON_TEXT: &str = "Save to file";
 const FROM_BUTTON_TEXT: &str = "Load from file";
+const SAVE_IMAGE_BUTTON_TEXT: &str = "Save image";
+const START_LOG_BUTTON_TEXT: &str = "Start event log";
+const STOP_LOG_BUTTON_TEXT: &str = "Stop event log";
 
 impl Framework {
     /// Create egui.
@@ -201,28 +243,65 @@ impl Gui {
     /// Pass in an arc mutex to the simulation grid. This is used to synchronize data access/display,
     /// as the data needs to be shared between the display and computation thread
     fn new(arc: Arc<Mutex<Dla>>) -> Self {
-        Self {
+        // start in sync with the simulation, which may have been set up from the command line
+        let guard = arc.lock().unwrap();
+        let (width, height) = guard.size();
+        let theme = guard.theme();
+        let spawn_radius = guard.spawn_radius();
+        let noise_reduction = guard.noise_reduction();
+        let temperature = guard.temperature();
+        let max_walk = guard.max_walk();
+        let evaporation = guard.evaporation();
+        let gui = Self {
             window_open: true,
             about_open: true,
-            paused: true,
-            grid_type: GridType::Center,
-            stuck_particles: 1,
-            particles: 10000,
-            complete: false,
-            width: 400,
-            height: 400,
+            analysis_open: false,
+            box_counting: None,
+            rg_fit: None,
+            rg_file: "".to_string(),
+            tree_summary: None,
+            lacunarity: None,
+            angular: None,
+            paused: guard.paused(),
+            grid_type: guard.grid_type(),
+            stuck_particles: guard.stuck_particles(),
+            particles: guard.particles(),
+            complete: guard.complete(),
+            width,
+            height,
             to_file: "".to_string(),
             from_file: "".to_string(),
-            particle_color: DEFAULT_PART_CLR,
-            background_color: DEFAULT_BACK_CLR,
-            spawn_radius: 0, // particles can spawn anywhere to start
-            enable_spawn_radius: false,
-            selected_width: 400,
-            selected_height: 400,
-            time_coloring: true,
-            arc,
-            theme: DEFAULT_THEME,
-        }
+            image_file: "".to_string(),
+            image_scale: 1,
+            event_log_file: "".to_string(),
+            event_logging: false,
+            particle_color: guard.particle_color(),
+            background_color: guard.background_color(),
+            spawn_radius: spawn_radius.unwrap_or(0), // particles can spawn anywhere to start
+            enable_spawn_radius: spawn_radius.is_some(),
+            noise_reduction: noise_reduction.unwrap_or(1), // every touch fills a site, same as classic DLA
+            enable_noise_reduction: noise_reduction.is_some(),
+            temperature: temperature.unwrap_or(1.0),
+            enable_temperature: temperature.is_some(),
+            max_walk: max_walk.unwrap_or(100_000),
+            enable_max_walk: max_walk.is_some(),
+            evaporation: evaporation.unwrap_or(0.0001),
+            enable_evaporation: evaporation.is_some(),
+            evaporated: guard.evaporated(),
+            selected_width: width as u32,
+            selected_height: height as u32,
+            time_coloring: theme.is_some(),
+            color_by_seed: false,
+            replay: false,
+            timeline: 0,
+            last_id: 0,
+            replay_playing: false,
+            replay_speed: 100,
+            arc: Arc::clone(&arc),
+            theme: theme.unwrap_or(DEFAULT_THEME),
+        };
+        std::mem::drop(guard);
+        gui
     }
 
     /// Create the UI using egui.
@@ -234,6 +313,10 @@ impl Gui {
                         self.window_open = true;
                         ui.close_menu();
                     }
+                    if ui.button("Analysis").clicked() {
+                        self.analysis_open = true;
+                        ui.close_menu();
+                    }
                     if ui.button("About").clicked() {
                         self.about_open = true;
                         ui.close_menu();
@@ -252,6 +335,200 @@ impl Gui {
                 ));
             });
 
+        egui::Window::new("Analysis")
+            .open(&mut self.analysis_open)
+            .show(ctx, |ui| {
+                // the radius of gyration is tracked as particles stick, so it's cheap to show live
+                const MAX_PLOT_POINTS: usize = 1000;
+                let (particles, rg, max_radius, plot_points) = {
+                    let guard = self.arc.lock().unwrap();
+                    let mass_radius = guard.mass_radius();
+                    let series = mass_radius.series();
+                    let step = std::cmp::max(series.len() / MAX_PLOT_POINTS, 1);
+                    let plot_points: Vec<[f64; 2]> = series
+                        .iter()
+                        .step_by(step)
+                        .map(|(n, rg)| [*n as f64, *rg])
+                        .collect();
+                    (
+                        mass_radius.count(),
+                        mass_radius.radius_of_gyration(),
+                        mass_radius.max_radius(),
+                        plot_points,
+                    )
+                };
+                ui.label(format!(
+                    "Particles: {}   Radius of gyration: {:.2}   Max radius: {}",
+                    particles, rg, max_radius
+                ));
+                egui::plot::Plot::new("rg-plot")
+                    .height(120.0)
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(egui::plot::Line::new(egui::plot::PlotPoints::new(
+                            plot_points,
+                        )))
+                    });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.rg_file));
+                    if ui
+                        .button("Export Rg series")
+                        .on_hover_text("Write particles vs radius of gyration as CSV")
+                        .clicked()
+                    {
+                        self.arc
+                            .lock()
+                            .unwrap()
+                            .handle_export_rg_clicked(&self.rg_file);
+                    }
+                });
+                ui.separator();
+
+                // seed ownership is tracked as particles stick too. Only worth showing with more than 1 seed
+                let seeds: Vec<(usize, usize, usize, Option<usize>)> = {
+                    let guard = self.arc.lock().unwrap();
+                    let ownership = guard.ownership();
+                    (0..ownership.seeds())
+                        .map(|seed| {
+                            (
+                                seed,
+                                ownership.mass(seed),
+                                ownership.cluster_of(seed),
+                                ownership.merged_at(seed).map(|m| m.id),
+                            )
+                        })
+                        .collect()
+                };
+                if seeds.len() > 1 {
+                    egui::Grid::new("seeds").striped(true).show(ui, |ui| {
+                        ui.label("Seed");
+                        ui.label("Mass");
+                        ui.label("Cluster");
+                        ui.label("Merged at");
+                        ui.end_row();
+                        for (seed, mass, cluster, merged_at) in seeds {
+                            ui.label(seed.to_string());
+                            ui.label(mass.to_string());
+                            ui.label(cluster.to_string());
+                            ui.label(match merged_at {
+                                Some(id) => format!("particle {}", id),
+                                None => "-".to_string(),
+                            });
+                            ui.end_row();
+                        }
+                    });
+                    ui.separator();
+                }
+
+                if ui
+                    .button("Compute")
+                    .on_hover_text("Measure the grid as it is right now")
+                    .clicked()
+                {
+                    const ANGULAR_BINS: usize = 72;
+                    const HARMONICS: usize = 8;
+                    let guard = self.arc.lock().unwrap();
+                    let grid = guard.grid();
+                    let sizes = analysis::default_box_sizes(grid);
+                    self.box_counting = Some(analysis::box_counting(grid, &sizes));
+                    self.lacunarity = Some(analysis::lacunarity(grid, &sizes));
+                    self.angular = Some(analysis::angular_distribution(
+                        grid,
+                        ANGULAR_BINS,
+                        HARMONICS,
+                    ));
+                    self.rg_fit = guard.mass_radius().fit();
+                    let tree = guard.aggregation_tree();
+                    self.tree_summary =
+                        Some((tree.tips().len(), tree.max_depth(), tree.mean_depth()));
+                }
+                ui.separator();
+
+                ui.label(match self.rg_fit {
+                    Some(fit) => format!(
+                        "Rg ~ N^{:.4} (R² {:.4}), dimension {:.4}",
+                        fit.slope,
+                        fit.r_squared,
+                        1.0 / fit.slope
+                    ),
+                    None => "Rg scaling: not enough particles".to_string(),
+                });
+                if let Some((tips, max_depth, mean_depth)) = self.tree_summary {
+                    ui.label(format!(
+                        "Aggregation tree: {} tips, max depth {}, mean depth {:.2}",
+                        tips, max_depth, mean_depth
+                    ));
+                }
+
+                if let Some(angular) = &self.angular {
+                    ui.label(match angular.dominant_symmetry() {
+                        Some(h) => format!(
+                            "Angular mass: strongest symmetry {}-fold ({:.4}), lopsidedness {:.4}",
+                            h.n, h.amplitude, angular.harmonics[0].amplitude
+                        ),
+                        None => "Angular mass: no particles".to_string(),
+                    });
+                    let bin_width = 360.0 / angular.bins.len() as f64;
+                    let points: Vec<[f64; 2]> = angular
+                        .bins
+                        .iter()
+                        .enumerate()
+                        .map(|(i, mass)| [(i as f64 + 0.5) * bin_width, *mass])
+                        .collect();
+                    egui::plot::Plot::new("angular-plot")
+                        .height(100.0)
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(egui::plot::Line::new(egui::plot::PlotPoints::new(points)))
+                        });
+                    egui::Grid::new("harmonics").striped(true).show(ui, |ui| {
+                        ui.label("n");
+                        for h in angular.harmonics.iter() {
+                            ui.label(h.n.to_string());
+                        }
+                        ui.end_row();
+                        ui.label("Amplitude");
+                        for h in angular.harmonics.iter() {
+                            ui.label(format!("{:.3}", h.amplitude));
+                        }
+                        ui.end_row();
+                    });
+                }
+
+                match &self.box_counting {
+                    Some(result) => {
+                        ui.label(match result.fit {
+                            Some(fit) => format!(
+                                "Box-counting dimension: {:.4} (R² {:.4})",
+                                fit.slope, fit.r_squared
+                            ),
+                            None => "Box-counting dimension: not enough box sizes".to_string(),
+                        });
+                        egui::Grid::new("box-counts").striped(true).show(ui, |ui| {
+                            ui.label("Box size");
+                            ui.label("Boxes");
+                            ui.label("Lacunarity");
+                            ui.end_row();
+                            for (size, count) in result.counts.iter() {
+                                ui.label(size.to_string());
+                                ui.label(count.to_string());
+                                let lacunarity = self.lacunarity.as_ref().and_then(|l| {
+                                    l.points.iter().find(|(s, _)| s == size).map(|(_, l)| *l)
+                                });
+                                ui.label(match lacunarity {
+                                    Some(lacunarity) => format!("{:.4}", lacunarity),
+                                    None => "-".to_string(),
+                                });
+                                ui.end_row();
+                            }
+                        });
+                    }
+                    None => {
+                        ui.label("Nothing computed yet");
+                    }
+                }
+            });
+
         egui::Window::new("Control Panel")
             .open(&mut self.window_open)
             .show(ctx, |ui| {
@@ -260,6 +537,15 @@ impl Gui {
                     Ok(guard) => {
                         self.paused = guard.paused();
                         self.stuck_particles = guard.stuck_particles();
+                        self.evaporated = guard.evaporated();
+                        self.event_logging = guard.event_logging();
+                        self.last_id = guard.last_id();
+                        // the backend turns the timeline off when the grid changes underneath it
+                        if let Some(timeline) = guard.timeline() {
+                            self.timeline = timeline;
+                        } else {
+                            self.replay = false;
+                        }
                         self.complete = guard.complete();
                         self.grid_type = guard.grid_type();
                         (self.width, self.height) = guard.size();
@@ -297,6 +583,16 @@ impl Gui {
                     });
                 });
                 ui.collapsing("Colors", |ui| {
+                    if ui
+                        .checkbox(&mut self.color_by_seed, "Color by seed")
+                        .on_hover_text("Particle color is based on which seed it grew from")
+                        .clicked()
+                    {
+                        self.arc
+                            .lock()
+                            .unwrap()
+                            .handle_color_by_seed_changed(self.color_by_seed);
+                    }
                     ui.horizontal(|ui| {
                         if ui
                             .checkbox(&mut self.time_coloring, "Theme:   ")
@@ -486,6 +782,128 @@ impl Gui {
                     }
                 });
 
+                // NOISE REDUCTION ------------------
+                ui.horizontal(|ui| {
+                    let old_enable_noise_reduction = self.enable_noise_reduction;
+                    ui.checkbox(&mut self.enable_noise_reduction, "Noise Reduction:")
+                        .on_hover_text(
+                            "A site only fills after walkers touched it this many times. Earlier walkers are removed",
+                        );
+                    ui.add_enabled_ui(self.enable_noise_reduction, |ui| {
+                        let old_noise_reduction = self.noise_reduction;
+                        ui.add(
+                            egui::DragValue::new(&mut self.noise_reduction)
+                                .speed(1)
+                                .clamp_range(RangeInclusive::new(1, 100)),
+                        );
+                        if old_noise_reduction != self.noise_reduction
+                            || old_enable_noise_reduction != self.enable_noise_reduction
+                        {
+                            // threshold updated, or it was just enabled
+                            self.arc
+                                .lock()
+                                .unwrap()
+                                .handle_noise_reduction_changed(Some(self.noise_reduction));
+                        }
+                    });
+                    if old_enable_noise_reduction != self.enable_noise_reduction
+                        && !self.enable_noise_reduction
+                    {
+                        // if it changed, and now it's off, tell the backend to go back to classic DLA
+                        self.arc
+                            .lock()
+                            .unwrap()
+                            .handle_noise_reduction_changed(None);
+                    }
+                });
+
+                // SURFACE RELAXATION ------------------
+                ui.horizontal(|ui| {
+                    let old_enable_temperature = self.enable_temperature;
+                    ui.checkbox(&mut self.enable_temperature, "Relaxation:")
+                        .on_hover_text(
+                            "Stuck particles with few neighbors can hop along the surface or detach. Higher temperatures move them more often",
+                        );
+                    ui.add_enabled_ui(self.enable_temperature, |ui| {
+                        let old_temperature = self.temperature;
+                        ui.add(
+                            egui::DragValue::new(&mut self.temperature)
+                                .speed(0.05)
+                                .clamp_range(RangeInclusive::new(0.01, 10.0)),
+                        );
+                        if old_temperature != self.temperature
+                            || old_enable_temperature != self.enable_temperature
+                        {
+                            // temperature updated, or it was just enabled
+                            self.arc
+                                .lock()
+                                .unwrap()
+                                .handle_temperature_changed(Some(self.temperature));
+                        }
+                    });
+                    if old_enable_temperature != self.enable_temperature && !self.enable_temperature {
+                        // if it changed, and now it's off, tell the backend to stop relaxing
+                        self.arc.lock().unwrap().handle_temperature_changed(None);
+                    }
+                });
+
+                // WALKER LIFETIME ------------------
+                ui.horizontal(|ui| {
+                    let old_enable_max_walk = self.enable_max_walk;
+                    ui.checkbox(&mut self.enable_max_walk, "Max Walk:")
+                        .on_hover_text("Walkers are removed after this many steps without sticking");
+                    ui.add_enabled_ui(self.enable_max_walk, |ui| {
+                        let old_max_walk = self.max_walk;
+                        ui.add(
+                            egui::DragValue::new(&mut self.max_walk)
+                                .speed(1000)
+                                .clamp_range(RangeInclusive::new(1, 100_000_000)),
+                        );
+                        if old_max_walk != self.max_walk || old_enable_max_walk != self.enable_max_walk
+                        {
+                            // max walk updated, or it was just enabled
+                            self.arc
+                                .lock()
+                                .unwrap()
+                                .handle_max_walk_changed(Some(self.max_walk));
+                        }
+                    });
+                    if old_enable_max_walk != self.enable_max_walk && !self.enable_max_walk {
+                        // if it changed, and now it's off, let walkers walk forever
+                        self.arc.lock().unwrap().handle_max_walk_changed(None);
+                    }
+
+                    let old_enable_evaporation = self.enable_evaporation;
+                    ui.checkbox(&mut self.enable_evaporation, "Evaporation:")
+                        .on_hover_text("Probability that a walker is removed on each step");
+                    ui.add_enabled_ui(self.enable_evaporation, |ui| {
+                        let old_evaporation = self.evaporation;
+                        ui.add(
+                            egui::DragValue::new(&mut self.evaporation)
+                                .speed(0.00001)
+                                // at 1 every walker evaporates before it can stick
+                                .clamp_range(RangeInclusive::new(0.0, 0.999)),
+                        );
+                        if old_evaporation != self.evaporation
+                            || old_enable_evaporation != self.enable_evaporation
+                        {
+                            // evaporation updated, or it was just enabled
+                            self.arc
+                                .lock()
+                                .unwrap()
+                                .handle_evaporation_changed(Some(self.evaporation));
+                        }
+                    });
+                    if old_enable_evaporation != self.enable_evaporation && !self.enable_evaporation {
+                        // if it changed, and now it's off, walkers don't evaporate anymore
+                        self.arc.lock().unwrap().handle_evaporation_changed(None);
+                    }
+                });
+                ui.label(format!(
+                    "Stuck particles: {}, evaporated walkers: {}",
+                    self.stuck_particles, self.evaporated
+                ));
+
                 ui.horizontal(|ui| {
                     // PAUSE/RESET BUTTON ------------------
                     ui.add_enabled_ui(
@@ -545,10 +963,114 @@ impl Gui {
                                 }
                             });
                             ui.separator();
+                            // PNG of the grid as it's drawn now
+                            ui.add(egui::TextEdit::singleline(&mut self.image_file));
+                            ui.horizontal(|ui| {
+                                ui.label("Scale");
+                                ui.add(egui::DragValue::new(&mut self.image_scale).clamp_range(1..=16));
+                                if ui.button(SAVE_IMAGE_BUTTON_TEXT).clicked() {
+                                    self.arc
+                                        .lock()
+                                        .unwrap()
+                                        .handle_save_image_clicked(&self.image_file, self.image_scale);
+                                }
+                            });
+                            ui.separator();
+                            // event log of stuck particles. Can be toggled while the simulation runs
+                            ui.add_enabled_ui(!self.event_logging, |ui| {
+                                ui.add(egui::TextEdit::singleline(&mut self.event_log_file));
+                            });
+                            if self.event_logging {
+                                if ui.button(STOP_LOG_BUTTON_TEXT).clicked() {
+                                    self.arc.lock().unwrap().handle_event_log_changed(None);
+                                }
+                            } else if ui
+                                .button(START_LOG_BUTTON_TEXT)
+                                .on_hover_text("Files ending in .csv are written as CSV, anything else is binary")
+                                .clicked()
+                            {
+                                self.arc
+                                    .lock()
+                                    .unwrap()
+                                    .handle_event_log_changed(Some(&self.event_log_file));
+                            }
+                            ui.separator();
                         });
                     }
                 });
 
+                // TIMELINE ------------------
+                // replay the growth of a paused/complete grid, using the order particles stuck
+                if self.replay && !(self.paused || self.complete) {
+                    // the simulation started running again, go back to showing everything
+                    self.replay = false;
+                    self.arc.lock().unwrap().handle_timeline_changed(None);
+                }
+                let old_timeline = self.timeline;
+                ui.add_enabled_ui(self.paused || self.complete, |ui| {
+                    ui.collapsing("Timeline", |ui| {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .checkbox(&mut self.replay, "Replay")
+                                .on_hover_text(
+                                    "Only show particles that stuck up to the selected point",
+                                )
+                                .changed()
+                            {
+                                self.replay_playing = false;
+                                self.timeline = self.last_id;
+                                self.arc
+                                    .lock()
+                                    .unwrap()
+                                    .handle_timeline_changed(if self.replay {
+                                        Some(self.timeline)
+                                    } else {
+                                        None
+                                    });
+                            }
+                            ui.add_enabled_ui(self.replay, |ui| {
+                                let button_text = if self.replay_playing {
+                                    "Pause"
+                                } else {
+                                    "Play"
+                                };
+                                if ui.button(button_text).clicked() {
+                                    if !self.replay_playing && self.timeline >= self.last_id {
+                                        // start over if we're at the end
+                                        self.timeline = 0;
+                                    }
+                                    self.replay_playing = !self.replay_playing;
+                                }
+                                ui.label("Speed:");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.replay_speed)
+                                        .speed(10)
+                                        .clamp_range(RangeInclusive::new(1, 100_000)),
+                                )
+                                .on_hover_text("Particles added per frame while playing");
+                            });
+                        });
+                        ui.add_enabled_ui(self.replay, |ui| {
+                            ui.add(
+                                egui::Slider::new(&mut self.timeline, 0..=self.last_id).text("id"),
+                            );
+                        });
+                    });
+                });
+                // keep playing even if the timeline section is collapsed
+                if self.replay && self.replay_playing {
+                    self.timeline = std::cmp::min(self.timeline + self.replay_speed, self.last_id);
+                    if self.timeline == self.last_id {
+                        self.replay_playing = false; // reached the end
+                    }
+                }
+                if self.replay && old_timeline != self.timeline {
+                    self.arc
+                        .lock()
+                        .unwrap()
+                        .handle_timeline_changed(Some(self.timeline));
+                }
+
                 // PROGRESS BAR ------------------
                 let progress: f32 = self.stuck_particles as f32 / self.particles as f32;
                 let progress_bar = egui::ProgressBar::new(progress).show_percentage();
@@ -0,0 +1,298 @@
+//! Harmonic measure and multifractal spectrum of an aggregate
+//!
+//! The harmonic measure of a perimeter site is the probability that the next walker sticks there, so it's the growth
+//! probability of the aggregate. It's estimated by launching probe walkers at a frozen grid and counting where they
+//! stick. The tips of a DLA cluster catch almost everything while the fjords between branches are screened, so the
+//! measure is famously multifractal: no single exponent describes how it scales.
+//!
+//! The measure is coarse-grained into boxes of a range of sizes ε. Writing μ_i for the measure in box i:
+//! - the generalized dimensions come from the partition function Σ μ_i^q ~ ε^τ(q), with D_q = τ(q) / (q - 1)
+//! - the f(α) spectrum is computed directly with the method of Chhabra and Jensen (1989), which avoids numerically
+//!   differentiating τ(q) for the Legendre transform
+use std::thread;
+
+use crate::analysis::{linear_fit, LinearFit};
+use crate::dla::Dla;
+use crate::grid::Grid;
+
+/// Hit counts of probe walkers launched at a grid
+#[derive(Clone, Debug)]
+pub struct HarmonicMeasure {
+    pub width: usize,
+    pub height: usize,
+    /// Number of probes that stuck at each grid index
+    pub counts: Vec<u64>,
+    /// Total number of probes that stuck
+    pub probes: u64,
+}
+
+/// One point of the multifractal spectrum, for a single moment order q
+#[derive(Clone, Copy, Debug)]
+pub struct SpectrumPoint {
+    pub q: f64,
+    /// Mass exponent: Σ μ_i^q ~ ε^τ(q)
+    pub tau: f64,
+    /// Generalized dimension D_q. D_0 is the dimension of the growth sites, D_1 the information dimension and D_2 the
+    /// correlation dimension
+    pub dimension: f64,
+    /// Singularity strength α(q)
+    pub alpha: f64,
+    /// Dimension f(α) of the set of sites with singularity strength α
+    pub f_alpha: f64,
+    /// Worst R² of the fits for this q
+    pub r_squared: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    pub points: Vec<SpectrumPoint>,
+}
+
+/// Launch `probes` walkers at `grid` and count where they'd stick. The probes are shared between all available cores.
+///
+/// Walkers spawn uniformly over the grid like they do in the simulation, or only outside `spawn_radius` if it's set.
+/// Spawning far away measures growth from outside the aggregate only, which is closer to the textbook harmonic
+/// measure.
+pub fn harmonic_measure(
+    grid: &Grid,
+    probes: usize,
+    spawn_radius: Option<usize>,
+) -> HarmonicMeasure {
+    let mut dla = Dla::from_grid(grid.clone());
+    dla.handle_spawn_radius_changed(spawn_radius);
+
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(probes.max(1));
+    let mut counts = vec![0_u64; grid.cells.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| {
+                // spread the remainder over the first few threads
+                let share = probes / threads + usize::from(t < probes % threads);
+                // clones share the random number generator's state, so they'd all launch the same probes
+                let mut dla = dla.clone();
+                dla.reseed(rand::random());
+                scope.spawn(move || dla.probe_growth_sites(share))
+            })
+            .collect();
+        for worker in workers {
+            let hits = worker.join().expect("Probe thread panicked");
+            for (count, hit) in counts.iter_mut().zip(hits) {
+                *count += hit;
+            }
+        }
+    });
+
+    HarmonicMeasure {
+        width: grid.width,
+        height: grid.height,
+        probes: counts.iter().sum(),
+        counts,
+    }
+}
+
+impl HarmonicMeasure {
+    /// Number of distinct sites that caught at least one probe
+    pub fn growth_sites(&self) -> usize {
+        self.counts.iter().filter(|&&c| c > 0).count()
+    }
+
+    /// The measure summed over boxes of edge `size`, normalized to 1. Empty boxes are left out
+    fn box_measure(&self, size: usize) -> Vec<f64> {
+        let boxes_x = self.width.div_ceil(size);
+        let boxes_y = self.height.div_ceil(size);
+        let mut boxes = vec![0_u64; boxes_x * boxes_y];
+        for (idx, count) in self.counts.iter().enumerate() {
+            let (x, y) = (idx % self.width, idx / self.width);
+            boxes[x / size + (y / size) * boxes_x] += count;
+        }
+        let total = self.probes as f64;
+        boxes
+            .into_iter()
+            .filter(|&b| b > 0)
+            .map(|b| b as f64 / total)
+            .collect()
+    }
+
+    /// Compute the generalized dimensions and f(α) spectrum for each q, fitting over box edges `sizes`.
+    ///
+    /// Returns None if no probes stuck or there are fewer than 2 box sizes. Negative q is dominated by the sites that
+    /// caught the fewest probes, so it needs a lot of probes to be meaningful.
+    pub fn spectrum(&self, qs: &[f64], sizes: &[usize]) -> Option<Spectrum> {
+        if self.probes == 0 || sizes.len() < 2 {
+            return None;
+        }
+
+        let log_sizes: Vec<f64> = sizes.iter().map(|&size| (size as f64).ln()).collect();
+        let measures: Vec<Vec<f64>> = sizes.iter().map(|&size| self.box_measure(size)).collect();
+
+        let mut points = Vec::new();
+        for &q in qs {
+            let mut log_z = Vec::new();
+            let mut entropy = Vec::new(); // Σ μ ln μ, for D_1
+            let mut alpha_sums = Vec::new();
+            let mut f_sums = Vec::new();
+            for measure in measures.iter() {
+                let z: f64 = measure.iter().map(|mu| mu.powf(q)).sum();
+                log_z.push(z.ln());
+                entropy.push(measure.iter().map(|mu| mu * mu.ln()).sum::<f64>());
+                // the measure reweighted to emphasize the boxes that dominate moment q
+                let (mut alpha_sum, mut f_sum) = (0.0, 0.0);
+                for mu in measure.iter() {
+                    let weight = mu.powf(q) / z;
+                    if weight == 0.0 {
+                        continue; // underflowed at large q, and contributes nothing anyway
+                    }
+                    alpha_sum += weight * mu.ln();
+                    f_sum += weight * weight.ln();
+                }
+                alpha_sums.push(alpha_sum);
+                f_sums.push(f_sum);
+            }
+
+            let fits: Vec<LinearFit> = [&log_z, &alpha_sums, &f_sums, &entropy]
+                .iter()
+                .map(|ys| linear_fit(&log_sizes, ys))
+                .collect::<Option<Vec<LinearFit>>>()?;
+            let tau = fits[0].slope;
+            // τ(1) is always 0, so D_1 comes from the entropy instead
+            let dimension = if (q - 1.0).abs() < 1e-9 {
+                fits[3].slope
+            } else {
+                tau / (q - 1.0)
+            };
+            points.push(SpectrumPoint {
+                q,
+                tau,
+                dimension,
+                alpha: fits[1].slope,
+                f_alpha: fits[2].slope,
+                r_squared: fits[..3]
+                    .iter()
+                    .map(|fit| fit.r_squared)
+                    .fold(1.0, f64::min),
+            });
+        }
+        Some(Spectrum { points })
+    }
+
+    /// Write one row per site that caught a probe: position, hits and measure
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "x,y,hits,measure")?;
+        for (idx, count) in self.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                idx % self.width,
+                idx / self.width,
+                count,
+                *count as f64 / self.probes as f64
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl Spectrum {
+    /// The point for moment order q, if it was computed
+    pub fn at(&self, q: f64) -> Option<&SpectrumPoint> {
+        self.points.iter().find(|p| (p.q - q).abs() < 1e-9)
+    }
+
+    pub fn to_csv(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "q,tau,dimension,alpha,f_alpha,r_squared")?;
+        for p in self.points.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                p.q, p.tau, p.dimension, p.alpha, p.f_alpha, p.r_squared
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [usize; 4] = [1, 2, 4, 8];
+
+    fn measure(width: usize, counts: Vec<u64>) -> HarmonicMeasure {
+        HarmonicMeasure {
+            width,
+            height: counts.len() / width,
+            probes: counts.iter().sum(),
+            counts,
+        }
+    }
+
+    /// A 16x16 binomial cascade: every box splits its measure between its quadrants in the ratio 1:2:3:4
+    fn cascade() -> HarmonicMeasure {
+        let counts = (0..16 * 16)
+            .map(|idx: usize| {
+                let (x, y) = (idx % 16, idx / 16);
+                (0..4)
+                    .map(|level| [1, 2, 3, 4][((y >> level) & 1) * 2 + ((x >> level) & 1)])
+                    .product()
+            })
+            .collect();
+        measure(16, counts)
+    }
+
+    #[test]
+    fn uniform_measure() {
+        // every site catches as much as any other, so all the dimensions are the dimension of the plane
+        let uniform = measure(16, vec![3; 16 * 16]);
+        let qs = [-2.0, 0.0, 1.0, 2.0, 5.0];
+        let spectrum = uniform.spectrum(&qs, &SIZES).unwrap();
+        for p in spectrum.points.iter() {
+            assert!(
+                (p.dimension - 2.0).abs() < 1e-9,
+                "D({}) = {}",
+                p.q,
+                p.dimension
+            );
+            assert!((p.alpha - 2.0).abs() < 1e-9, "α({}) = {}", p.q, p.alpha);
+            assert!((p.f_alpha - 2.0).abs() < 1e-9, "f({}) = {}", p.q, p.f_alpha);
+            assert!((p.tau - 2.0 * (p.q - 1.0)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cascade_spectrum() {
+        let weights = [0.1_f64, 0.2, 0.3, 0.4];
+        let qs = [-30.0, -1.0, 0.0, 1.0, 2.0, 30.0];
+        let spectrum = cascade().spectrum(&qs, &SIZES).unwrap();
+        for p in spectrum.points.iter() {
+            let z: f64 = weights.iter().map(|w| w.powf(p.q)).sum();
+            assert!((p.tau + z.log2()).abs() < 1e-9, "τ({}) = {}", p.q, p.tau);
+            // f is the Legendre transform of τ
+            assert!((p.f_alpha - (p.q * p.alpha - p.tau)).abs() < 1e-9);
+        }
+        let d = |q: f64| spectrum.at(q).unwrap().dimension;
+        assert!((d(0.0) - 2.0).abs() < 1e-9);
+        assert!(d(-1.0) > d(0.0) && d(0.0) > d(1.0) && d(1.0) > d(2.0));
+
+        // the curve ends at the singularities of the densest and emptiest boxes, where only one box is left
+        let alpha_min = -0.4_f64.log2();
+        let alpha_max = -0.1_f64.log2();
+        let high = spectrum.at(30.0).unwrap();
+        let low = spectrum.at(-30.0).unwrap();
+        assert!((high.alpha - alpha_min).abs() < 1e-3, "{}", high.alpha);
+        assert!((low.alpha - alpha_max).abs() < 1e-3, "{}", low.alpha);
+        assert!(high.f_alpha.abs() < 1e-2 && low.f_alpha.abs() < 1e-2);
+    }
+
+    #[test]
+    fn no_spectrum_without_probes() {
+        assert!(measure(4, vec![0; 16]).spectrum(&[1.0], &SIZES).is_none());
+        assert!(cascade().spectrum(&[1.0], &[1]).is_none());
+    }
+}
@@ -0,0 +1,429 @@
+//! PNG export of the grid as it's drawn on screen, and import of grids from those PNGs
+//!
+//! Images are encoded in pure Rust, so they can be written from a headless run on a machine without a display. Exports
+//! carry the run in text chunks: the config that grows it again, the seed, particle count, grid type, theme and the id
+//! of every particle, so an image doubles as a save file. Any other PNG can be read in too, as long as its background
+//! is the most common color, but the order its particles stuck in has to be guessed.
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use clap::Parser;
+
+use crate::colors::Color;
+use crate::components;
+use crate::config::DlaConfig;
+use crate::grid::{Grid, Particle};
+use crate::gridfile::{GridFile, Parameters};
+
+/// Text chunk keywords
+pub const SOFTWARE: &str = "Software";
+pub const CONFIG: &str = "Config";
+pub const SEED: &str = "Seed";
+pub const PARTICLES: &str = "Particles";
+pub const GRID_TYPE: &str = "Grid Type";
+pub const THEME: &str = "Theme";
+/// Pixels per grid cell along each edge
+pub const SCALE: &str = "Scale";
+/// Background color as r,g,b,a. Every other color is a particle
+pub const BACKGROUND: &str = "Background";
+/// Id of every particle drawn, in row-major order
+pub const IDS: &str = "Ids";
+
+/// Text longer than this goes in a compressed zTXt chunk instead of tEXt
+const COMPRESS_TEXT_OVER: usize = 1024;
+
+/// Scale an RGBA image up by an integer factor, so each pixel becomes a `scale` x `scale` square
+pub fn upscale(rgba: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    if scale <= 1 {
+        return rgba.to_vec();
+    }
+    let mut scaled = Vec::with_capacity(rgba.len() * scale * scale);
+    for row in rgba.chunks_exact(width * 4).take(height) {
+        let mut scaled_row = Vec::with_capacity(row.len() * scale);
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(pixel);
+            }
+        }
+        for _ in 0..scale {
+            scaled.extend_from_slice(&scaled_row);
+        }
+    }
+    scaled
+}
+
+/// Write an RGBA image to a PNG file, scaled up by `scale`, with a text chunk for each (keyword, text)
+pub fn write_png(
+    file_name: &str,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    scale: usize,
+    text: &[(&str, String)],
+) -> std::io::Result<()> {
+    let scale = scale.max(1);
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(file_name)?),
+        (width * scale) as u32,
+        (height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk(SCALE.to_string(), scale.to_string())?;
+    for (keyword, text) in text.iter() {
+        if text.len() > COMPRESS_TEXT_OVER {
+            encoder.add_ztxt_chunk(keyword.to_string(), text.clone())?;
+        } else {
+            encoder.add_text_chunk(keyword.to_string(), text.clone())?;
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&upscale(rgba, width, height, scale))?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Ids of the particles to store in an image, in row-major order
+pub fn ids_text(grid: &Grid, last_visible: usize) -> String {
+    let ids: Vec<String> = grid
+        .cells
+        .iter()
+        .filter(|c| c.filled && c.id <= last_visible)
+        .map(|c| c.id.to_string())
+        .collect();
+    ids.join(" ")
+}
+
+/// A color as it's stored in the Background chunk
+pub fn color_text(color: Color) -> String {
+    format!("{},{},{},{}", color[0], color[1], color[2], color[3])
+}
+
+fn parse_color(text: &str) -> Option<Color> {
+    let channels: Vec<u8> = text
+        .split(',')
+        .filter_map(|c| c.trim().parse().ok())
+        .collect();
+    channels.try_into().ok()
+}
+
+/// A decoded PNG
+struct PngImage {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+    /// Text chunks by keyword
+    text: HashMap<String, String>,
+}
+
+/// Read a PNG file into RGBA pixels
+fn read_png(file_name: &str) -> Option<PngImage> {
+    let file = match File::open(file_name) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Error encountered reading from filesystem: {:?}", err);
+            return None;
+        }
+    };
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // palettes and low bit depths come out as 8 bit RGB(A) or grayscale
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(err) => {
+            eprintln!("Error encountered decoding image {}: {:?}", file_name, err);
+            return None;
+        }
+    };
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = match reader.next_frame(&mut buffer) {
+        Ok(frame) => frame,
+        Err(err) => {
+            eprintln!("Error encountered decoding image {}: {:?}", file_name, err);
+            return None;
+        }
+    };
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let rgba: Vec<u8> = match frame.color_type {
+        png::ColorType::Rgba => buffer[..width * height * 4].to_vec(),
+        png::ColorType::Rgb => buffer[..width * height * 3]
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer[..width * height * 2]
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => buffer[..width * height]
+            .iter()
+            .flat_map(|&p| [p, p, p, 255])
+            .collect(),
+    };
+
+    let info = reader.info();
+    let mut text = HashMap::new();
+    for chunk in info.uncompressed_latin1_text.iter() {
+        text.insert(chunk.keyword.clone(), chunk.text.clone());
+    }
+    for chunk in info.compressed_latin1_text.iter() {
+        if let Ok(value) = chunk.get_text() {
+            text.insert(chunk.keyword.clone(), value);
+        }
+    }
+    Some(PngImage {
+        width,
+        height,
+        rgba,
+        text,
+    })
+}
+
+/// Rebuild a grid from a PNG. Cells that aren't the background color are particles. Their ids come from the Ids chunk
+/// if it's there and matches the particles in the image, otherwise they're guessed with `infer_ids`. Without a Scale
+/// chunk the scale is worked out from the pixels
+pub fn grid_from_png(file_name: &str) -> Option<GridFile> {
+    let PngImage {
+        width: image_width,
+        height: image_height,
+        rgba,
+        text,
+    } = read_png(file_name)?;
+    let scale = text
+        .get(SCALE)
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&s| s > 0 && image_width % s == 0 && image_height % s == 0)
+        .unwrap_or_else(|| infer_scale(&rgba, image_width, image_height));
+    let (width, height) = (image_width / scale, image_height / scale);
+    let background = text
+        .get(BACKGROUND)
+        .and_then(|c| parse_color(c))
+        .unwrap_or_else(|| most_common_color(&rgba));
+
+    // one pixel from each cell's square is enough, they're all the same color
+    let cells: Vec<Particle> = (0..width * height)
+        .map(|idx| {
+            let (x, y) = (idx % width * scale, idx / width * scale);
+            let pixel = (x + y * image_width) * 4;
+            Particle {
+                filled: rgba[pixel..pixel + 4] != background,
+                id: 0,
+            }
+        })
+        .collect();
+    let mut grid = Grid {
+        cells,
+        width,
+        height,
+    };
+
+    let filled: Vec<usize> = (0..grid.cells.len()).filter(|&i| grid.filled(i)).collect();
+    let ids: Option<Vec<usize>> = text.get(IDS).and_then(|ids| {
+        ids.split_whitespace()
+            .map(|id| id.parse().ok())
+            .collect::<Option<Vec<usize>>>()
+    });
+    match ids {
+        Some(ids) if ids.len() == filled.len() => {
+            for (idx, id) in filled.into_iter().zip(ids) {
+                grid.cells[idx].id = id;
+            }
+        }
+        _ => {
+            eprintln!(
+                "No particle order stored in {}, guessing it from the shape of the aggregate",
+                file_name
+            );
+            infer_ids(&mut grid);
+        }
+    }
+    Some(GridFile {
+        grid,
+        version: None,
+        parameters: parameters(&text),
+    })
+}
+
+/// The settings of the run that grew an exported image, from its text chunks. The rest come from its config
+fn parameters(text: &HashMap<String, String>) -> Parameters {
+    let config = text.get(CONFIG).and_then(|args| {
+        DlaConfig::try_parse_from(std::iter::once("dla").chain(args.split_whitespace())).ok()
+    });
+    Parameters {
+        software: text.get(SOFTWARE).cloned(),
+        grid_type: text.get(GRID_TYPE).cloned(),
+        // the particle chunk is how many had stuck when the image was drawn, the config has how many the run grows
+        particles: config
+            .as_ref()
+            .map(|c| c.particles)
+            .or_else(|| text.get(PARTICLES).and_then(|p| p.parse().ok())),
+        spawn_radius: config.as_ref().and_then(|c| c.spawn_radius),
+        seed: text.get(SEED).and_then(|s| s.parse().ok()),
+        color: config.as_ref().and_then(|c| c.color.clone()),
+        background_color: config.as_ref().and_then(|c| c.background_color.clone()),
+        theme: text.get(THEME).filter(|t| *t != "None").cloned(),
+        config: text.get(CONFIG).cloned(),
+    }
+}
+
+/// The largest scale the image could have been upscaled by: every run of same colored pixels, along rows and columns,
+/// is a multiple of it
+fn infer_scale(rgba: &[u8], width: usize, height: usize) -> usize {
+    fn gcd(a: usize, b: usize) -> usize {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    let pixel = |x: usize, y: usize| &rgba[(x + y * width) * 4..(x + y * width) * 4 + 4];
+    let mut scale = gcd(width, height);
+    for y in 0..height {
+        let mut run = 1;
+        for x in 1..width {
+            if pixel(x, y) == pixel(x - 1, y) {
+                run += 1;
+            } else {
+                scale = gcd(scale, run);
+                run = 1;
+            }
+        }
+    }
+    for x in 0..width {
+        let mut run = 1;
+        for y in 1..height {
+            if pixel(x, y) == pixel(x, y - 1) {
+                run += 1;
+            } else {
+                scale = gcd(scale, run);
+                run = 1;
+            }
+        }
+    }
+    scale.max(1)
+}
+
+fn most_common_color(rgba: &[u8]) -> Color {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for pixel in rgba.chunks_exact(4) {
+        *counts
+            .entry([pixel[0], pixel[1], pixel[2], pixel[3]])
+            .or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(color, _)| color)
+        .unwrap_or([0, 0, 0, 255])
+}
+
+/// Guess the order particles stuck in when it isn't known. In each connected piece, the particle closest to the
+/// piece's centroid becomes its seed (id 0), and ids count up along the aggregate from there, so particles further
+/// out are younger. That's right for the shape of the aggregation tree, not for the exact order of growth
+pub fn infer_ids(grid: &mut Grid) {
+    let components = components::label(grid);
+    let mut sums = vec![(0.0, 0.0); components.sizes.len()];
+    for (idx, label) in components.labels.iter().enumerate() {
+        if let Some(c) = label {
+            sums[*c].0 += (idx % grid.width) as f64;
+            sums[*c].1 += (idx / grid.width) as f64;
+        }
+    }
+    let mut seeds: Vec<Option<(f64, usize)>> = vec![None; components.sizes.len()];
+    for (idx, label) in components.labels.iter().enumerate() {
+        if let Some(c) = label {
+            let size = components.sizes[*c] as f64;
+            let (dx, dy) = (
+                (idx % grid.width) as f64 - sums[*c].0 / size,
+                (idx / grid.width) as f64 - sums[*c].1 / size,
+            );
+            let distance = dx * dx + dy * dy;
+            if seeds[*c].is_none_or(|(best, _)| distance < best) {
+                seeds[*c] = Some((distance, idx));
+            }
+        }
+    }
+
+    let mut next_id = 1;
+    let mut visited = vec![false; grid.cells.len()];
+    let mut queue = VecDeque::new();
+    for (_, seed) in seeds.into_iter().flatten() {
+        grid.cells[seed].id = 0;
+        visited[seed] = true;
+        queue.push_back(seed);
+        while let Some(idx) = queue.pop_front() {
+            let (x, y) = ((idx % grid.width) as isize, (idx / grid.width) as isize);
+            for ny in y - 1..=y + 1 {
+                for nx in x - 1..=x + 1 {
+                    if nx < 0 || ny < 0 || nx >= grid.width as isize || ny >= grid.height as isize {
+                        continue;
+                    }
+                    let n_idx = nx as usize + ny as usize * grid.width;
+                    if grid.filled(n_idx) && !visited[n_idx] {
+                        visited[n_idx] = true;
+                        grid.cells[n_idx].id = next_id;
+                        next_id += 1;
+                        queue.push_back(n_idx);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dla::Dla;
+
+    /// Grow a small aggregate, export it at `scale` and read it back
+    fn round_trip(scale: usize) {
+        let config = DlaConfig {
+            width: 60,
+            particles: 400,
+            seed: Some(5),
+            ..Default::default()
+        };
+        let mut sim = Dla::from(&config);
+        sim.run();
+        let (width, height) = sim.size();
+        let text = sim.image_text();
+        let file =
+            std::env::temp_dir().join(format!("dla-png-{}-{}.png", scale, std::process::id()));
+        let file = file.to_string_lossy();
+        write_png(&file, width, height, &sim.render(), scale, &text).unwrap();
+
+        let image = read_png(&file).unwrap();
+        assert_eq!((image.width, image.height), (width * scale, height * scale));
+        assert_eq!(image.text[SCALE], scale.to_string());
+        assert_eq!(image.text[SEED], "5");
+        assert_eq!(image.text[PARTICLES], sim.stuck_particles().to_string());
+        assert_eq!(image.text[GRID_TYPE], "Center");
+        // long enough to be compressed
+        assert!(image.text[IDS].len() > COMPRESS_TEXT_OVER);
+        assert_eq!(image.text[IDS], ids_text(sim.grid(), usize::MAX));
+
+        let loaded = grid_from_png(&file).unwrap();
+        std::fs::remove_file(&*file).unwrap();
+        assert_eq!(loaded.parameters.seed, Some(5));
+        assert_eq!(loaded.parameters.grid_type.as_deref(), Some("Center"));
+        assert_eq!(loaded.parameters.particles, Some(400));
+        assert_eq!((loaded.grid.width, loaded.grid.height), (width, height));
+        for (loaded, grown) in loaded.grid.cells.iter().zip(sim.grid().cells.iter()) {
+            assert_eq!(loaded.filled, grown.filled);
+            if grown.filled {
+                assert_eq!(loaded.id, grown.id);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_at_scale_1() {
+        round_trip(1);
+    }
+
+    #[test]
+    fn round_trip_scaled_up() {
+        round_trip(3);
+    }
+}
@@ -1,7 +1,25 @@
+pub mod analysis;
+pub mod animation;
+pub mod branches;
 pub mod colors;
+mod commands;
+pub mod components;
 pub mod config;
 mod dla;
+mod ensemble;
+pub mod events;
 pub mod grid;
+pub mod gridfile;
+pub mod harmonic;
+pub mod image;
+pub mod numpy;
+pub mod plot;
+pub mod portable;
+pub mod stl;
+pub mod stream;
+pub mod svg;
+mod sweep;
+pub mod tree;
 use dla::Dla;
 
 use log::{debug, error};
@@ -25,20 +43,59 @@ fn main() {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         console_log::init_with_level(log::Level::Trace).expect("error initializing logger");
 
-        wasm_bindgen_futures::spawn_local(run());
+        wasm_bindgen_futures::spawn_local(run(Dla::default(), 1.25));
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     {
+        use clap::Parser;
+        use config::View;
         env_logger::init();
 
-        pollster::block_on(run());
+        let config = config::DlaConfig::parse();
+        if let Err(err) = config.validate() {
+            eprintln!("Invalid settings: {}", err);
+            return;
+        }
+        if let Some(command) = &config.command {
+            // commands don't need a window
+            commands::run(command, &config);
+            return;
+        }
+        if config.benchmark {
+            Dla::benchmark();
+            return;
+        }
+
+        // the outputs are written when the simulation completes, whichever way it's viewed. A loaded grid is already
+        // complete, so it's written right away
+        let mut sim = Dla::from(&config);
+        if let Some(from_file) = &config.from_file {
+            sim.handle_from_button_clicked(from_file);
+            if sim.complete() {
+                sim.write_outputs();
+            }
+        }
+        match config.view() {
+            View::Live => (),
+            View::End => {
+                sim.run();
+                sim.print_summary();
+                sim.write_outputs();
+            }
+            View::Skip => {
+                sim.run();
+                sim.print_summary();
+                sim.write_outputs();
+                return;
+            }
+        }
+
+        pollster::block_on(run(sim, config.scale_factor));
     }
 }
 
-async fn run() {
-    let sim: Dla = Dla::default();
-    let scale_factor = 1.25;
+async fn run(sim: Dla, scale_factor: f64) {
     let (width, height) = sim.size();
 
     let event_loop = EventLoop::new();
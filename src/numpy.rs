@@ -0,0 +1,224 @@
+//! Exports for analysis in Python: NumPy arrays of the grid and a CSV list of particles
+//!
+//! Arrays are .npy files (format version 1.0) with shape (height, width), so `grid[y, x]` is the cell at (x, y):
+//! - the mask is bool, true for particles
+//! - ids are int64, the particle's id or -1 for an empty cell
+//!
+//! Both load with `numpy.load`. The particle list has a row per particle: id, x, y, parent, update and steps. Parent is
+//! the id of the particle it's attached to, left empty for seeds and particles whose parent detached. With an event log
+//! it's the particle the walker actually attached to, otherwise it's inferred from the grid (see tree::parent_of).
+//! Update (when it stuck) and steps (how long it walked) only come from an event log, and are empty without one.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::events::StickEvent;
+use crate::grid::Grid;
+use crate::tree::{self, AggregationTree};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Write an array in .npy format. `descr` is the NumPy dtype string, and `data` is the array's bytes in C order
+fn write_npy(
+    file_name: &str,
+    descr: &str,
+    shape: (usize, usize),
+    data: &[u8],
+) -> std::io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, shape.0, shape.1
+    );
+    // the header is padded with spaces and ends in a newline, so the data starts at a multiple of 64 bytes. The
+    // magic, version and header length take 10
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(file_name)?);
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+/// Write which cells are filled as a bool array
+pub fn write_mask(file_name: &str, grid: &Grid) -> std::io::Result<()> {
+    let data: Vec<u8> = grid.cells.iter().map(|cell| cell.filled as u8).collect();
+    write_npy(file_name, "|b1", (grid.height, grid.width), &data)
+}
+
+/// Write particle ids as an int64 array, with -1 for empty cells
+pub fn write_ids(file_name: &str, grid: &Grid) -> std::io::Result<()> {
+    let data: Vec<u8> = grid
+        .cells
+        .iter()
+        .flat_map(|cell| {
+            let id = if cell.filled { cell.id as i64 } else { -1 };
+            id.to_le_bytes()
+        })
+        .collect();
+    write_npy(file_name, "<i8", (grid.height, grid.width), &data)
+}
+
+/// Write a CSV with a row per particle, seeds first and then in the order they stuck. `events` fill in when and to what
+/// each particle stuck, matched by id
+pub fn write_particles(
+    file_name: &str,
+    grid: &Grid,
+    events: Option<&[StickEvent]>,
+) -> std::io::Result<()> {
+    let tree = AggregationTree::from_grid(grid, None);
+    let events: HashMap<usize, &StickEvent> = events
+        .unwrap_or(&[])
+        .iter()
+        .map(|event| (event.id, event))
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(file_name)?);
+    writeln!(writer, "id,x,y,parent,update,steps")?;
+    // the tree's root stands in for all the seeds, so they're listed separately
+    let seeds = (0..grid.cells.len()).filter(|&idx| grid.filled(idx) && grid.cells[idx].id == 0);
+    for idx in seeds {
+        writeln!(writer, "0,{},{},,,", idx % grid.width, idx / grid.width)?;
+    }
+    for node in 1..tree.ids.len() {
+        let idx = tree.cells[node].unwrap();
+        let id = tree.ids[node];
+        let event = events.get(&id);
+        let parent = match event {
+            Some(event) => attached_to(grid, event.neighbor, id),
+            None => match tree.parents[node].unwrap() {
+                // the root isn't a real parent, only a seed cell next to it is
+                0 if !next_to_seed(grid, idx) => String::new(),
+                parent => tree.ids[parent].to_string(),
+            },
+        };
+        let (update, steps) = match event {
+            Some(event) => (event.update.to_string(), event.steps.to_string()),
+            None => (String::new(), String::new()),
+        };
+        writeln!(
+            writer,
+            "{},{},{},{},{},{}",
+            id,
+            idx % grid.width,
+            idx / grid.width,
+            parent,
+            update,
+            steps
+        )?;
+    }
+    writer.flush()
+}
+
+/// The id of the particle at `neighbor`, which particle `id` stuck to. Empty if it's no longer there: the cell is empty
+/// or holds a particle that stuck later
+fn attached_to(grid: &Grid, neighbor: (usize, usize), id: usize) -> String {
+    let (x, y) = neighbor;
+    if x >= grid.width || y >= grid.height {
+        return String::new();
+    }
+    let cell = &grid.cells[y * grid.width + x];
+    if cell.filled && cell.id < id {
+        cell.id.to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Is a seed one of the 8 neighbors of `idx`?
+fn next_to_seed(grid: &Grid, idx: usize) -> bool {
+    tree::parent_of(grid, idx).is_some_and(|p| grid.cells[p].id == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+
+    fn scratch(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("dla-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// A 5x3 grid with a seed at (1, 1), particles 1 and 2 to its right and particle 3 above 1
+    fn grid() -> Grid {
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            15
+        ];
+        for (x, y, id) in [(1, 1, 0), (2, 1, 1), (3, 1, 2), (2, 0, 3)] {
+            cells[x + y * 5] = Particle { filled: true, id };
+        }
+        Grid {
+            cells,
+            width: 5,
+            height: 3,
+        }
+    }
+
+    #[test]
+    fn npy_header() {
+        let grid = grid();
+        for (name, bytes) in [("mask.npy", 1), ("ids.npy", 8)] {
+            let file = scratch(name);
+            if bytes == 1 {
+                write_mask(&file, &grid).unwrap();
+            } else {
+                write_ids(&file, &grid).unwrap();
+            }
+            let data = std::fs::read(&file).unwrap();
+            std::fs::remove_file(&file).unwrap();
+
+            assert_eq!(&data[..6], NPY_MAGIC);
+            assert_eq!(&data[6..8], [1, 0]);
+            let header_len = u16::from_le_bytes([data[8], data[9]]) as usize;
+            let start = 10 + header_len;
+            assert_eq!(start % 64, 0);
+            assert_eq!(data[start - 1], b'\n');
+            let header = std::str::from_utf8(&data[10..start]).unwrap();
+            assert!(header.contains("'shape': (3, 5)"), "{}", header);
+            assert_eq!(data.len() - start, 15 * bytes);
+        }
+    }
+
+    /// The parent column of each particle row, seeds skipped
+    fn parents(events: Option<&[StickEvent]>) -> Vec<String> {
+        let file = scratch("particles.csv");
+        write_particles(&file, &grid(), events).unwrap();
+        let text = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        text.lines()
+            .skip(2)
+            .map(|row| row.split(',').nth(3).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parent_column() {
+        // inferred from the grid: 3 touches the seed, 1 and 2, and the seed is oldest
+        assert_eq!(parents(None), ["0", "1", "0"]);
+
+        let event = |id, pos, neighbor| StickEvent {
+            id,
+            pos,
+            update: 10 * id as u64,
+            steps: id as u64,
+            neighbor,
+        };
+        // 3 actually stuck to 2, and 2 to a particle that's gone since. 1 has no event, so it's inferred
+        let events = [event(2, (3, 1), (4, 1)), event(3, (2, 0), (3, 1))];
+        assert_eq!(parents(Some(&events)), ["0", "", "2"]);
+    }
+}
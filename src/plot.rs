@@ -0,0 +1,197 @@
+//! Pen plotter and laser cutter export: the aggregate as continuous strokes along the aggregation tree
+//!
+//! Each link between a particle and its parent (see tree::parent_of) is drawn exactly once, as a line between the
+//! centers of their cells. Links are joined into as few strokes as a tree allows, so the pen lifts as little as
+//! possible: a stroke runs from one branch tip, back toward the seed and out to another tip. Where branches meet, the
+//! longest one carries on toward the seed and the rest are paired up with each other.
+//!
+//! Strokes are written as SVG polylines, or as HPGL for files ending in .hpgl or .plt. Both are in millimeters.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::grid::Grid;
+use crate::tree::{self, AggregationTree};
+
+/// HPGL plotter units per millimeter
+const HPGL_UNITS_PER_MM: f64 = 40.0;
+
+/// The aggregate as strokes, each a list of grid indices to draw through in order
+#[derive(Clone, Debug)]
+pub struct Strokes {
+    pub strokes: Vec<Vec<usize>>,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Join the links of `grid`'s aggregation tree into strokes
+pub fn strokes(grid: &Grid) -> Strokes {
+    let tree = AggregationTree::from_grid(grid, None);
+    let mut strokes = Vec::new();
+    // chains that end at each node, coming up from its children. Each runs from a tip to the node's cell
+    let mut pending: Vec<Vec<Vec<usize>>> = vec![Vec::new(); tree.ids.len()];
+    // and the chains that end at each seed cell
+    let mut at_seeds: HashMap<usize, Vec<Vec<usize>>> = HashMap::new();
+
+    // children always come after their parents, so going backwards every node's children are done before it
+    for node in (1..tree.ids.len()).rev() {
+        let cell = tree.cells[node].unwrap();
+        let mut chains = std::mem::take(&mut pending[node]);
+        if chains.is_empty() {
+            chains.push(Vec::new());
+        }
+        for chain in chains.iter_mut() {
+            chain.push(cell);
+        }
+        let up = join_at_node(chains, &mut strokes);
+
+        let parent = tree.parents[node].unwrap();
+        if parent != 0 {
+            pending[parent].push(up);
+            continue;
+        }
+        // the root stands in for every seed, so the link goes to the seed cell the particle touches, if any
+        let seed = tree::parent_of(grid, cell).filter(|&p| grid.cells[p].id == 0);
+        match seed {
+            Some(seed) => {
+                let mut up = up;
+                up.push(seed);
+                at_seeds.entry(seed).or_default().push(up);
+            }
+            // its parent detached. A single particle has nothing to draw
+            None if up.len() > 1 => strokes.push(up),
+            None => (),
+        }
+    }
+
+    let mut seeds: Vec<(usize, Vec<Vec<usize>>)> = at_seeds.into_iter().collect();
+    seeds.sort_unstable_by_key(|(seed, _)| *seed);
+    // nothing carries on past a seed, so every chain is paired
+    for (_, chains) in seeds {
+        pair_up(chains, &mut strokes);
+    }
+
+    Strokes {
+        strokes,
+        width: grid.width,
+        height: grid.height,
+    }
+}
+
+/// Chains that all end at the same cell. The longest one is returned to carry on past it, and the rest are paired up
+fn join_at_node(mut chains: Vec<Vec<usize>>, strokes: &mut Vec<Vec<usize>>) -> Vec<usize> {
+    chains.sort_unstable_by_key(|chain| std::cmp::Reverse(chain.len()));
+    let longest = chains.remove(0);
+    pair_up(chains, strokes);
+    longest
+}
+
+/// Join chains that all end at the same cell in pairs, longest first, into strokes through the cell. With an odd number
+/// of chains, the shortest ends there as its own stroke
+fn pair_up(mut chains: Vec<Vec<usize>>, strokes: &mut Vec<Vec<usize>>) {
+    chains.sort_unstable_by_key(|chain| std::cmp::Reverse(chain.len()));
+    for pair in chains.chunks(2) {
+        let mut stroke = pair[0].clone();
+        if let Some(other) = pair.get(1) {
+            // both end at this cell, so go out along the second one backwards
+            stroke.extend(other.iter().rev().skip(1));
+        }
+        strokes.push(stroke);
+    }
+}
+
+impl Strokes {
+    /// Each stroke as points (x, y) in millimeters, with points in the middle of straight lines left out
+    pub fn polylines(&self, cell_size: f64) -> Vec<Vec<(f64, f64)>> {
+        self.strokes
+            .iter()
+            .map(|stroke| {
+                let points: Vec<(isize, isize)> = stroke
+                    .iter()
+                    .map(|&idx| ((idx % self.width) as isize, (idx / self.width) as isize))
+                    .collect();
+                let mut kept = Vec::with_capacity(points.len());
+                for (i, &point) in points.iter().enumerate() {
+                    let straight = i > 0 && i + 1 < points.len() && {
+                        let (prev, next) = (points[i - 1], points[i + 1]);
+                        (point.0 - prev.0, point.1 - prev.1) == (next.0 - point.0, next.1 - point.1)
+                    };
+                    if !straight {
+                        kept.push((
+                            (point.0 as f64 + 0.5) * cell_size,
+                            (point.1 as f64 + 0.5) * cell_size,
+                        ));
+                    }
+                }
+                kept
+            })
+            .collect()
+    }
+
+    /// Total length of every stroke in cells
+    pub fn length(&self) -> f64 {
+        self.polylines(1.0)
+            .iter()
+            .flat_map(|points| points.windows(2))
+            .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+            .sum()
+    }
+
+    /// Write the strokes as SVG polylines. Each cell is `cell_size` millimeters wide, and lines are `pen_width` wide
+    pub fn to_svg(&self, file_name: &str, cell_size: f64, pen_width: f64) -> std::io::Result<()> {
+        let (width, height) = (
+            self.width as f64 * cell_size,
+            self.height as f64 * cell_size,
+        );
+        let mut writer = BufWriter::new(File::create(file_name)?);
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+            w = width,
+            h = height
+        )?;
+        writeln!(
+            writer,
+            r#"<g fill="none" stroke="black" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round">"#,
+            pen_width
+        )?;
+        for points in self.polylines(cell_size) {
+            let points: Vec<String> = points
+                .iter()
+                .map(|(x, y)| format!("{:.3},{:.3}", x, y))
+                .collect();
+            writeln!(writer, r#"<polyline points="{}"/>"#, points.join(" "))?;
+        }
+        writeln!(writer, "</g>")?;
+        writeln!(writer, "</svg>")?;
+        writer.flush()
+    }
+
+    /// Write the strokes as HPGL with pen 1. HPGL's y axis points up, so the grid is flipped to keep it the right way
+    /// up on paper
+    pub fn to_hpgl(&self, file_name: &str, cell_size: f64) -> std::io::Result<()> {
+        let height = self.height as f64 * cell_size;
+        let units = |(x, y): (f64, f64)| {
+            (
+                (x * HPGL_UNITS_PER_MM).round() as i64,
+                ((height - y) * HPGL_UNITS_PER_MM).round() as i64,
+            )
+        };
+        let mut writer = BufWriter::new(File::create(file_name)?);
+        writeln!(writer, "IN;SP1;")?;
+        for points in self.polylines(cell_size) {
+            let mut points = points.into_iter().map(units);
+            let (x, y) = match points.next() {
+                Some(start) => start,
+                None => continue,
+            };
+            writeln!(writer, "PU{},{};", x, y)?;
+            let rest: Vec<String> = points.map(|(x, y)| format!("{},{}", x, y)).collect();
+            if !rest.is_empty() {
+                writeln!(writer, "PD{};", rest.join(","))?;
+            }
+        }
+        writeln!(writer, "PU;SP0;")?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,380 @@
+//! Grid formats other tools can read: PBM, PGM and ASCII art
+//!
+//! - .pbm: a bitmap of which cells are filled, 1 (black) for particles
+//! - .pgm: a 16-bit graymap of particle ids. Each particle is its id + 1, so seeds are 1 and empty cells are 0. Values
+//!   aren't scaled to the max value, so they read back as ids directly
+//! - .txt: ASCII art, one line per row, # for particles and . for empty cells
+//!
+//! Exports are binary PBM and PGM (P4 and P5). Imports also take the plain versions (P1 and P2) and 8-bit PGMs. When
+//! reading ASCII art, spaces, . and 0 are empty and anything else is a particle. PBM and ASCII art don't have ids, so the
+//! order particles stuck in is guessed the same way as for PNGs without them.
+use std::fs;
+
+use crate::grid::{Grid, Particle};
+use crate::image;
+
+/// Largest value a 16-bit PGM can hold. Ids past this - 1 are written as this
+const PGM_MAX: usize = u16::MAX as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Pbm,
+    Pgm,
+    Ascii,
+}
+
+impl Format {
+    /// The format of a file from its extension (case insensitive), if it's one of these
+    pub fn from_path(file_name: &str) -> Option<Self> {
+        let lower = file_name.to_ascii_lowercase();
+        if lower.ends_with(".pbm") {
+            Some(Format::Pbm)
+        } else if lower.ends_with(".pgm") {
+            Some(Format::Pgm)
+        } else if lower.ends_with(".txt") {
+            Some(Format::Ascii)
+        } else {
+            None
+        }
+    }
+}
+
+/// Write `grid` in `format`
+pub fn write(file_name: &str, grid: &Grid, format: Format) -> std::io::Result<()> {
+    let bytes = match format {
+        Format::Pbm => pbm(grid),
+        Format::Pgm => {
+            let max_id = grid.cells.iter().map(|c| c.id).max().unwrap_or(0);
+            if max_id >= PGM_MAX {
+                eprintln!(
+                    "Ids past {} don't fit in a 16-bit PGM, those particles are written as {}",
+                    PGM_MAX - 1,
+                    PGM_MAX
+                );
+            }
+            pgm(grid)
+        }
+        Format::Ascii => ascii(grid).into_bytes(),
+    };
+    fs::write(file_name, bytes)
+}
+
+fn pbm(grid: &Grid) -> Vec<u8> {
+    let mut bytes = format!("P4\n{} {}\n", grid.width, grid.height).into_bytes();
+    // rows are padded out to a whole byte, the first cell is the highest bit
+    for row in grid.cells.chunks(grid.width) {
+        for byte in row.chunks(8) {
+            let bits = byte
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.filled)
+                .fold(0u8, |bits, (i, _)| bits | (0x80 >> i));
+            bytes.push(bits);
+        }
+    }
+    bytes
+}
+
+fn pgm(grid: &Grid) -> Vec<u8> {
+    let mut bytes = format!("P5\n{} {}\n{}\n", grid.width, grid.height, PGM_MAX).into_bytes();
+    for cell in grid.cells.iter() {
+        let value = if cell.filled {
+            (cell.id + 1).min(PGM_MAX)
+        } else {
+            0
+        };
+        // 16-bit samples are big endian
+        bytes.extend((value as u16).to_be_bytes());
+    }
+    bytes
+}
+
+fn ascii(grid: &Grid) -> String {
+    let mut text = String::with_capacity((grid.width + 1) * grid.height);
+    for row in grid.cells.chunks(grid.width) {
+        text.extend(row.iter().map(|cell| if cell.filled { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+/// Read a grid in `format`. Errors are printed, like Grid::from_file does
+pub fn read(file_name: &str, format: Format) -> Option<Grid> {
+    let bytes = match fs::read(file_name) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Error encountered reading {}: {:?}", file_name, err);
+            return None;
+        }
+    };
+    let result = match format {
+        Format::Pbm | Format::Pgm => read_netpbm(&bytes),
+        Format::Ascii => read_ascii(&String::from_utf8_lossy(&bytes)),
+    };
+    match result {
+        Ok(grid) => Some(grid),
+        Err(err) => {
+            eprintln!("Error encountered reading {}: {}", file_name, err);
+            None
+        }
+    }
+}
+
+/// Reads the whitespace separated header of a netpbm file, skipping comments
+struct Header<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Header<'a> {
+    fn token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'#' => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.pos += 1;
+        }
+        Some(&self.bytes[start..self.pos])
+    }
+
+    fn number(&mut self, what: &str) -> Result<usize, String> {
+        self.token()
+            .and_then(|t| std::str::from_utf8(t).ok())
+            .and_then(|t| t.parse().ok())
+            .ok_or(format!("missing or bad {}", what))
+    }
+}
+
+fn read_netpbm(bytes: &[u8]) -> Result<Grid, String> {
+    let mut header = Header { bytes, pos: 0 };
+    let magic = header.token().ok_or("empty file")?.to_vec();
+    let width = header.number("width")?;
+    let height = header.number("height")?;
+    if width == 0 || height == 0 {
+        return Err("the image is empty".to_string());
+    }
+    let size = width * height;
+
+    let mut cells = vec![
+        Particle {
+            filled: false,
+            id: 0
+        };
+        size
+    ];
+    let mut has_ids = true;
+    match magic.as_slice() {
+        b"P1" => {
+            has_ids = false;
+            // bits don't need whitespace between them
+            let mut bits = bytes[header.pos..]
+                .iter()
+                .filter(|b| matches!(b, b'0' | b'1'));
+            for cell in cells.iter_mut() {
+                cell.filled = *bits.next().ok_or("not enough pixels")? == b'1';
+            }
+        }
+        b"P4" => {
+            has_ids = false;
+            // one whitespace byte after the header
+            let data = bytes.get(header.pos + 1..).unwrap_or(&[]);
+            let row_bytes = width.div_ceil(8);
+            if data.len() < row_bytes * height {
+                return Err("not enough pixels".to_string());
+            }
+            for (idx, cell) in cells.iter_mut().enumerate() {
+                let (x, y) = (idx % width, idx / width);
+                cell.filled = data[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0;
+            }
+        }
+        b"P2" => {
+            header.number("max value")?;
+            for cell in cells.iter_mut() {
+                set_value(cell, header.number("pixel")?);
+            }
+        }
+        b"P5" => {
+            let max = header.number("max value")?;
+            let data = bytes.get(header.pos + 1..).unwrap_or(&[]);
+            let sample = if max < 256 { 1 } else { 2 };
+            if data.len() < size * sample {
+                return Err("not enough pixels".to_string());
+            }
+            for (cell, value) in cells.iter_mut().zip(data.chunks(sample)) {
+                let value = value.iter().fold(0, |v, &b| (v << 8) | b as usize);
+                set_value(cell, value);
+            }
+        }
+        _ => return Err("not a PBM or PGM file".to_string()),
+    }
+
+    let mut grid = Grid {
+        cells,
+        width,
+        height,
+    };
+    if !has_ids {
+        image::infer_ids(&mut grid);
+    }
+    Ok(grid)
+}
+
+/// A PGM value is the particle's id + 1, or 0 for an empty cell
+fn set_value(cell: &mut Particle, value: usize) {
+    cell.filled = value > 0;
+    cell.id = value.saturating_sub(1);
+}
+
+fn read_ascii(text: &str) -> Result<Grid, String> {
+    let mut lines: Vec<&str> = text.lines().collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let height = lines.len();
+    if width == 0 {
+        return Err("there are no cells".to_string());
+    }
+
+    let mut cells = Vec::with_capacity(width * height);
+    for line in lines {
+        let row_start = cells.len();
+        cells.extend(line.chars().map(|c| Particle {
+            filled: !matches!(c, ' ' | '.' | '0'),
+            id: 0,
+        }));
+        // short lines are empty past their end
+        cells.resize(
+            row_start + width,
+            Particle {
+                filled: false,
+                id: 0,
+            },
+        );
+    }
+    let mut grid = Grid {
+        cells,
+        width,
+        height,
+    };
+    image::infer_ids(&mut grid);
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10x3 grid, so PBM rows don't fill a whole number of bytes
+    fn small_grid() -> Grid {
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            30
+        ];
+        for (idx, id) in [(14, 0), (15, 1), (5, 2), (9, 3), (25, 4), (26, 5)] {
+            cells[idx] = Particle { filled: true, id };
+        }
+        Grid {
+            cells,
+            width: 10,
+            height: 3,
+        }
+    }
+
+    fn mask(grid: &Grid) -> Vec<bool> {
+        grid.cells.iter().map(|cell| cell.filled).collect()
+    }
+
+    fn ids(grid: &Grid) -> Vec<Option<usize>> {
+        grid.cells
+            .iter()
+            .map(|cell| cell.filled.then_some(cell.id))
+            .collect()
+    }
+
+    /// The cells as plain netpbm values, one row per line
+    fn plain(grid: &Grid, value: impl Fn(&Particle) -> usize) -> String {
+        grid.cells
+            .chunks(grid.width)
+            .map(|row| {
+                let values: Vec<String> = row.iter().map(|cell| value(cell).to_string()).collect();
+                values.join(" ") + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pbm_round_trip() {
+        let grid = small_grid();
+
+        let read = read_netpbm(&pbm(&grid)).unwrap();
+        assert_eq!((read.width, read.height), (10, 3));
+        assert_eq!(mask(&read), mask(&grid));
+
+        let text = format!(
+            "P1\n# a comment\n10 3\n{}",
+            plain(&grid, |cell| cell.filled.into())
+        );
+        let read = read_netpbm(text.as_bytes()).unwrap();
+        assert_eq!(mask(&read), mask(&grid));
+    }
+
+    #[test]
+    fn pgm_round_trip() {
+        let grid = small_grid();
+
+        let read = read_netpbm(&pgm(&grid)).unwrap();
+        assert_eq!((read.width, read.height), (10, 3));
+        assert_eq!(ids(&read), ids(&grid));
+
+        let values = plain(&grid, |cell| if cell.filled { cell.id + 1 } else { 0 });
+        let text = format!("P2\n10 3\n{}\n{}", PGM_MAX, values);
+        let read = read_netpbm(text.as_bytes()).unwrap();
+        assert_eq!(ids(&read), ids(&grid));
+
+        // 8-bit samples
+        let mut bytes = b"P5\n10 3\n255\n".to_vec();
+        bytes.extend(
+            grid.cells
+                .iter()
+                .map(|cell| if cell.filled { cell.id as u8 + 1 } else { 0 }),
+        );
+        let read = read_netpbm(&bytes).unwrap();
+        assert_eq!(ids(&read), ids(&grid));
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let grid = small_grid();
+        let read = read_ascii(&ascii(&grid)).unwrap();
+        assert_eq!((read.width, read.height), (10, 3));
+        assert_eq!(mask(&read), mask(&grid));
+    }
+
+    #[test]
+    fn bad_netpbm() {
+        assert!(read_netpbm(b"P6\n1 1\n255\n\0\0\0").is_err());
+        assert!(read_netpbm(b"P4\n10 3\n\0").is_err());
+        assert!(read_netpbm(b"P1\n0 3\n").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! STL export for 3D printing: the aggregate as columns on a base plate
+//!
+//! Every cell of the grid is part of the base plate, and every particle is a square column on top of it. Columns are
+//! all the same height, or scale with particle id so the print shows the order the aggregate grew in. The mesh is the
+//! surface of that height field: tops, the walls between cells of different heights, and one bottom face.
+//!
+//! The mesh is watertight: every edge is shared by exactly 2 triangles, which meet it with their vertices at the same
+//! points. Walls are split at the height of every cell around their corners, and faces with extra points on their
+//! edges are fanned from their center, so no vertex ever sits in the middle of another triangle's edge. Particles that
+//! only touch diagonally would meet along a zero-width edge, which can't be printed and isn't a valid mesh, so the
+//! taller of the 2 cells beside them is raised to join them.
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::grid::Grid;
+
+type Point = [f64; 3];
+
+/// How tall each part of the model is, in millimeters
+#[derive(Clone, Copy, Debug)]
+pub struct Heights {
+    /// Edge length of a cell
+    pub cell_size: f64,
+    /// Thickness of the base plate
+    pub base: f64,
+    /// Height of columns above the plate. With by_id, the height of the newest particle
+    pub column: f64,
+    /// Scale column heights with particle id, from min_column for the seeds up to column for the newest particle
+    pub by_id: bool,
+    pub min_column: f64,
+}
+
+/// A triangle mesh
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub triangles: Vec<[Point; 3]>,
+}
+
+/// Height of the top of every cell above the bottom of the plate
+fn height_map(grid: &Grid, heights: &Heights) -> Vec<f64> {
+    let max_id = grid.cells.iter().map(|c| c.id).max().unwrap_or(0).max(1) as f64;
+    let mut map: Vec<f64> = grid
+        .cells
+        .iter()
+        .map(|cell| {
+            if !cell.filled {
+                heights.base
+            } else if heights.by_id {
+                let fraction = cell.id as f64 / max_id;
+                heights.base + heights.min_column + (heights.column - heights.min_column) * fraction
+            } else {
+                heights.base + heights.column
+            }
+        })
+        .collect();
+    join_diagonals(&mut map, grid.width, grid.height);
+    map
+}
+
+/// Raise cells until no corner has only a diagonal pair of cells above some height
+fn join_diagonals(map: &mut [f64], width: usize, height: usize) {
+    // corners are between cells, corner (x, y) is the top left of cell (x, y)
+    let mut stack: Vec<(usize, usize)> = (1..height)
+        .flat_map(|y| (1..width).map(move |x| (x, y)))
+        .collect();
+    while let Some((x, y)) = stack.pop() {
+        // around the corner, clockwise from the top left
+        let cells = [
+            (x - 1) + (y - 1) * width,
+            x + (y - 1) * width,
+            x + y * width,
+            (x - 1) + y * width,
+        ];
+        let h = cells.map(|c| map[c]);
+        for (high, low) in [((0, 2), (1, 3)), ((1, 3), (0, 2))] {
+            let top = h[high.0].min(h[high.1]);
+            if top > h[low.0].max(h[low.1]) {
+                let raise = if h[low.0] >= h[low.1] { low.0 } else { low.1 };
+                map[cells[raise]] = top;
+                // that may have made a new diagonal at the raised cell's other corners
+                let (cx, cy) = (cells[raise] % width, cells[raise] / width);
+                for (nx, ny) in [(cx, cy), (cx + 1, cy), (cx, cy + 1), (cx + 1, cy + 1)] {
+                    if nx >= 1 && ny >= 1 && nx < width && ny < height {
+                        stack.push((nx, ny));
+                    }
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Build the mesh of `grid` as columns on a base plate
+pub fn mesh(grid: &Grid, heights: &Heights) -> Mesh {
+    let (width, height) = (grid.width, grid.height);
+    let map = height_map(grid, heights);
+    // outside the grid counts as height 0
+    let at = |x: isize, y: isize| -> f64 {
+        if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+            0.0
+        } else {
+            map[x as usize + y as usize * width]
+        }
+    };
+    // distinct heights of the cells around corner (x, y), lowest first
+    let corner_heights = |x: usize, y: usize| -> Vec<f64> {
+        let (x, y) = (x as isize, y as isize);
+        let mut hs = vec![at(x - 1, y - 1), at(x, y - 1), at(x, y), at(x - 1, y)];
+        hs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        hs.dedup();
+        hs
+    };
+    let s = heights.cell_size;
+    // grid y grows downward, so it's flipped to keep the model the same way around as the screen and images
+    let point =
+        |x: usize, y: usize, z: f64| -> Point { [x as f64 * s, (height - y) as f64 * s, z] };
+    let mut mesh = Mesh::default();
+
+    // tops, one face per run of cells with the same height in a row. A corner on the run's edge needs a vertex if
+    // anything around it has a different height, since that's where walls and other faces end
+    for y in 0..height {
+        let mut x0 = 0;
+        while x0 < width {
+            let h = map[x0 + y * width];
+            let mut x1 = x0 + 1;
+            while x1 < width && map[x1 + y * width] == h {
+                x1 += 1;
+            }
+            let needs_vertex =
+                |x: usize, cy: usize| x == x0 || x == x1 || corner_heights(x, cy).len() > 1;
+            let mut boundary: Vec<Point> = (x0..=x1)
+                .filter(|&x| needs_vertex(x, y))
+                .map(|x| point(x, y, h))
+                .collect();
+            boundary.extend(
+                (x0..=x1)
+                    .rev()
+                    .filter(|&x| needs_vertex(x, y + 1))
+                    .map(|x| point(x, y + 1, h)),
+            );
+            mesh.fan(&boundary, [0.0, 0.0, 1.0]);
+            x0 = x1;
+        }
+    }
+
+    // walls between cells of different heights, including the outside of the grid. Vertical edges between x - 1 and
+    // x, then horizontal edges between y - 1 and y
+    for y in 0..height {
+        for x in 0..=width {
+            let (left, right) = (at(x as isize - 1, y as isize), at(x as isize, y as isize));
+            if left != right {
+                let normal = if left > right {
+                    [1.0, 0.0, 0.0]
+                } else {
+                    [-1.0, 0.0, 0.0]
+                };
+                mesh.wall(
+                    (x, y),
+                    (x, y + 1),
+                    left.min(right),
+                    left.max(right),
+                    normal,
+                    &corner_heights,
+                    &point,
+                );
+            }
+        }
+    }
+    for y in 0..=height {
+        for x in 0..width {
+            let (up, down) = (at(x as isize, y as isize - 1), at(x as isize, y as isize));
+            if up != down {
+                // facing down the grid is facing -y in the model
+                let normal = if up > down {
+                    [0.0, -1.0, 0.0]
+                } else {
+                    [0.0, 1.0, 0.0]
+                };
+                mesh.wall(
+                    (x, y),
+                    (x + 1, y),
+                    up.min(down),
+                    up.max(down),
+                    normal,
+                    &corner_heights,
+                    &point,
+                );
+            }
+        }
+    }
+
+    // the bottom, with every corner along the outside of the grid, since the outer walls end at each of them
+    let mut bottom: Vec<Point> = (0..width).map(|x| point(x, 0, 0.0)).collect();
+    bottom.extend((0..height).map(|y| point(width, y, 0.0)));
+    bottom.extend((1..=width).rev().map(|x| point(x, height, 0.0)));
+    bottom.extend((1..=height).rev().map(|y| point(0, y, 0.0)));
+    mesh.fan(&bottom, [0.0, 0.0, -1.0]);
+
+    mesh
+}
+
+impl Mesh {
+    /// Triangulate a flat convex face from its center. `boundary` goes around the face in either direction, and can
+    /// have points in the middle of its sides. Triangles are wound to face `normal`
+    fn fan(&mut self, boundary: &[Point], normal: Point) {
+        let n = boundary.len() as f64;
+        let center = boundary.iter().fold([0.0; 3], |c, p| {
+            [c[0] + p[0] / n, c[1] + p[1] / n, c[2] + p[2] / n]
+        });
+        for i in 0..boundary.len() {
+            let (a, b) = (boundary[i], boundary[(i + 1) % boundary.len()]);
+            let facing = dot(cross(sub(a, center), sub(b, center)), normal);
+            self.triangles.push(if facing >= 0.0 {
+                [center, a, b]
+            } else {
+                [center, b, a]
+            });
+        }
+    }
+
+    /// A wall along the edge between corners `a` and `b`, from height `low` to `high`, facing `normal`. Its sides are
+    /// split at the height of every cell around their corner
+    #[allow(clippy::too_many_arguments)]
+    fn wall(
+        &mut self,
+        a: (usize, usize),
+        b: (usize, usize),
+        low: f64,
+        high: f64,
+        normal: Point,
+        corner_heights: &impl Fn(usize, usize) -> Vec<f64>,
+        point: &impl Fn(usize, usize, f64) -> Point,
+    ) {
+        let side = |(x, y): (usize, usize)| -> Vec<f64> {
+            let mut hs: Vec<f64> = corner_heights(x, y)
+                .into_iter()
+                .filter(|&h| h > low && h < high)
+                .collect();
+            hs.insert(0, low);
+            hs.push(high);
+            hs
+        };
+        // up side b, then down side a
+        let mut boundary: Vec<Point> = side(b).into_iter().map(|z| point(b.0, b.1, z)).collect();
+        boundary.extend(side(a).into_iter().rev().map(|z| point(a.0, a.1, z)));
+        self.fan(&boundary, normal);
+    }
+
+    /// Write the mesh as binary STL
+    pub fn to_stl(&self, file_name: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_name)?);
+        let mut header = [0u8; 80];
+        let text = b"DLA aggregate height field, units are millimeters";
+        header[..text.len()].copy_from_slice(text);
+        writer.write_all(&header)?;
+        writer.write_u32::<LittleEndian>(self.triangles.len() as u32)?;
+        for triangle in self.triangles.iter() {
+            let normal = normalize(cross(
+                sub(triangle[1], triangle[0]),
+                sub(triangle[2], triangle[0]),
+            ));
+            for value in normal.iter().chain(triangle.iter().flatten()) {
+                writer.write_f32::<LittleEndian>(*value as f32)?;
+            }
+            // attribute byte count, unused
+            writer.write_u16::<LittleEndian>(0)?;
+        }
+        writer.flush()
+    }
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Point, b: Point) -> Point {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: Point, b: Point) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: Point) -> Point {
+    let length = dot(a, a).sqrt();
+    if length == 0.0 {
+        a
+    } else {
+        [a[0] / length, a[1] / length, a[2] / length]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+    use std::collections::HashMap;
+
+    type Key = [i64; 3];
+
+    fn key(p: Point) -> Key {
+        p.map(|v| (v * 1e6).round() as i64)
+    }
+
+    /// How many times each directed edge is used by a triangle
+    fn edges(mesh: &Mesh) -> HashMap<(Key, Key), usize> {
+        let mut edges = HashMap::new();
+        for t in mesh.triangles.iter() {
+            for i in 0..3 {
+                *edges.entry((key(t[i]), key(t[(i + 1) % 3]))).or_insert(0) += 1;
+            }
+        }
+        edges
+    }
+
+    /// Volume enclosed by the mesh. Positive when the triangles face outward
+    fn volume(mesh: &Mesh) -> f64 {
+        mesh.triangles
+            .iter()
+            .map(|t| dot(t[0], cross(t[1], t[2])) / 6.0)
+            .sum()
+    }
+
+    fn grid(width: usize, height: usize, particles: &[(usize, usize)]) -> Grid {
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            width * height
+        ];
+        for (id, (x, y)) in particles.iter().enumerate() {
+            cells[x + y * width] = Particle { filled: true, id };
+        }
+        Grid {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    const HEIGHTS: Heights = Heights {
+        cell_size: 1.0,
+        base: 2.0,
+        column: 3.0,
+        by_id: false,
+        min_column: 1.0,
+    };
+
+    #[test]
+    fn plate() {
+        let mesh = mesh(&grid(3, 2, &[]), &HEIGHTS);
+        assert!((volume(&mesh) - 3.0 * 2.0 * 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn watertight() {
+        // a seed, a branch and a particle only touching it diagonally, with columns of different heights
+        let particles = [(2, 2), (3, 2), (3, 3), (4, 4), (1, 1)];
+        for by_id in [false, true] {
+            let heights = Heights { by_id, ..HEIGHTS };
+            let mesh = mesh(&grid(6, 6, &particles), &heights);
+            let edges = edges(&mesh);
+            for ((a, b), count) in edges.iter() {
+                // every edge is used once in each direction, so 2 triangles share it and face the same way
+                assert_eq!(*count, 1, "edge {:?} -> {:?}", a, b);
+                assert_eq!(edges.get(&(*b, *a)), Some(&1), "edge {:?} -> {:?}", a, b);
+            }
+            assert!(volume(&mesh) > 6.0 * 6.0 * 2.0);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Stream of frames written as the simulation runs, for piping into a video encoder
+//!
+//! Frames are the same RGBA buffer Dla::draw fills for the window, one every K particles that stick or every N updates.
+//! Two formats are supported:
+//! - raw: frames back to back, width * height * 4 bytes each, with no header. The encoder needs the size and frame
+//!   rate, for example: ffmpeg -f rawvideo -pixel_format rgba -video_size 400x400 -framerate 30 -i - out.mp4
+//! - y4m: a YUV4MPEG2 stream (4:2:0, BT.601 limited range) that carries its own size and frame rate, for example:
+//!   ffmpeg -i - out.mp4
+//!
+//! The stream goes to a file, or to standard output if the path is `-`.
+use std::fs::File;
+use std::io::{BufWriter, Stdout, Write};
+
+/// Path that sends the stream to standard output
+pub const STDOUT: &str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamFormat {
+    Raw,
+    Y4m,
+}
+
+impl StreamFormat {
+    /// Parse a format name (case insensitive). With no name, files ending in .y4m are Y4M and anything else is raw
+    pub fn from(name: Option<&str>, path: &str) -> Option<Self> {
+        match name.map(|n| n.to_ascii_lowercase()).as_deref() {
+            Some("raw") => Some(StreamFormat::Raw),
+            Some("y4m") => Some(StreamFormat::Y4m),
+            Some(_) => None,
+            None if path.to_ascii_lowercase().ends_with(".y4m") => Some(StreamFormat::Y4m),
+            None => Some(StreamFormat::Raw),
+        }
+    }
+}
+
+/// When the stream takes a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// Every this many particles that stick
+    Particles(usize),
+    /// Every this many updates
+    Updates(u64),
+}
+
+impl Trigger {
+    /// Does the stream want a frame after this update? `stuck` is whether a particle stuck during it
+    pub fn fires(&self, updates: u64, stuck_particles: usize, stuck: bool) -> bool {
+        match *self {
+            Trigger::Particles(k) => stuck && stuck_particles.is_multiple_of(k.max(1)),
+            Trigger::Updates(n) => updates.is_multiple_of(n.max(1)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Output {
+    Stdout(BufWriter<Stdout>),
+    File(BufWriter<File>),
+}
+
+impl Output {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Output::Stdout(w) => w,
+            Output::File(w) => w,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FrameStream {
+    output: Output,
+    format: StreamFormat,
+    /// Frames per second written in the Y4M header
+    fps: u32,
+    /// Size of the frames, set by the first one. Every frame has to be the same size
+    size: Option<(usize, usize)>,
+    frames: usize,
+    /// Where the stream is being written, for error messages
+    path: String,
+}
+
+impl FrameStream {
+    /// Create a new stream at `path`, overwriting anything that's there
+    pub fn create(path: &str, format: StreamFormat, fps: u32) -> std::io::Result<Self> {
+        let output = if path == STDOUT {
+            Output::Stdout(BufWriter::new(std::io::stdout()))
+        } else {
+            Output::File(BufWriter::new(File::create(path)?))
+        };
+        Ok(Self {
+            output,
+            format,
+            fps: fps.max(1),
+            size: None,
+            frames: 0,
+            path: path.to_string(),
+        })
+    }
+
+    /// Append an RGBA frame to the stream
+    pub fn write_frame(&mut self, width: usize, height: usize, rgba: &[u8]) -> std::io::Result<()> {
+        match self.size {
+            None => {
+                self.size = Some((width, height));
+                if self.format == StreamFormat::Y4m {
+                    writeln!(
+                        self.output.writer(),
+                        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg",
+                        width,
+                        height,
+                        self.fps
+                    )?;
+                }
+            }
+            Some(size) if size != (width, height) => {
+                return Err(std::io::Error::other(format!(
+                    "frames are {}x{}, the stream is {}x{}",
+                    width, height, size.0, size.1
+                )));
+            }
+            Some(_) => (),
+        }
+
+        let format = self.format;
+        let writer = self.output.writer();
+        match format {
+            StreamFormat::Raw => writer.write_all(rgba)?,
+            StreamFormat::Y4m => {
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&rgba_to_yuv420(width, height, rgba))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Make sure every frame written so far has gone out
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.writer().flush()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn to_stdout(&self) -> bool {
+        matches!(self.output, Output::Stdout(_))
+    }
+}
+
+/// Convert RGBA to planar YUV 4:2:0 with BT.601 limited range coefficients. Alpha is ignored. Each chroma sample is
+/// the average of a 2x2 block of pixels. Odd sizes round the chroma planes up
+fn rgba_to_yuv420(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut y_plane = Vec::with_capacity(width * height);
+    let mut u_sums = vec![(0.0, 0); chroma_width * chroma_height];
+    let mut v_sums = vec![0.0; chroma_width * chroma_height];
+    for (idx, pixel) in rgba.chunks_exact(4).enumerate().take(width * height) {
+        let (r, g, b) = (pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+        y_plane.push((16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8);
+        let chroma = (idx % width) / 2 + (idx / width) / 2 * chroma_width;
+        u_sums[chroma].0 += 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+        u_sums[chroma].1 += 1;
+        v_sums[chroma] += 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    }
+    let u_plane = u_sums
+        .iter()
+        .map(|(sum, n)| (sum / *n as f64).round() as u8);
+    let v_plane = v_sums
+        .iter()
+        .zip(u_sums.iter())
+        .map(|(sum, (_, n))| (sum / *n as f64).round() as u8);
+    y_plane.extend(u_plane);
+    y_plane.extend(v_plane);
+    y_plane
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn frame(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.iter().flatten().copied().collect()
+    }
+
+    #[test]
+    fn yuv_of_solid_colors() {
+        // the standard BT.601 limited range values
+        for (color, yuv) in [
+            (BLACK, [16, 128, 128]),
+            (WHITE, [235, 128, 128]),
+            (RED, [82, 90, 240]),
+        ] {
+            let planes = rgba_to_yuv420(2, 2, &frame(&[color; 4]));
+            assert_eq!(planes, [yuv[0], yuv[0], yuv[0], yuv[0], yuv[1], yuv[2]]);
+        }
+    }
+
+    #[test]
+    fn yuv_chroma_is_averaged() {
+        // 3x3 has a 2x2 chroma plane, the right column and bottom row averaging fewer pixels
+        let pixels = [RED, BLACK, RED, RED, BLACK, RED, BLACK, BLACK, WHITE];
+        let planes = rgba_to_yuv420(3, 3, &frame(&pixels));
+        assert_eq!(planes.len(), 9 + 4 + 4);
+        assert_eq!(&planes[..9], [82, 16, 82, 82, 16, 82, 16, 16, 235]);
+        // top left is half red, top right all red, the bottom all black or white
+        assert_eq!(&planes[9..13], [109, 90, 128, 128]);
+        assert_eq!(&planes[13..], [184, 240, 128, 128]);
+    }
+}
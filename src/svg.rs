@@ -0,0 +1,134 @@
+//! SVG export, for figures that need to scale, like posters
+//!
+//! Every particle is drawn as a circle or a square, colored the same way it's drawn on screen. Particles are grouped by
+//! color so each color is written once. Merging draws each horizontal run of neighboring particles with the same
+//! color as one line through their centers, 1 wide with round or square caps, so a run of 1 is still a circle or
+//! square. All the lines of a color go in one path, which keeps large aggregates to a manageable file size.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::colors::Color;
+use crate::grid::Grid;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Circle,
+    Square,
+}
+
+impl Shape {
+    /// Parse a shape name (case insensitive)
+    pub fn from(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "circle" => Some(Shape::Circle),
+            "square" => Some(Shape::Square),
+            _ => None,
+        }
+    }
+}
+
+/// The color as an SVG hex color. Alpha is left out, the simulation's colors are opaque
+fn hex(color: &[u8]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Write the particles of `grid` to an SVG file. `rgba` is the grid drawn by Dla::render, which gives the colors.
+/// Each cell is `scale` units wide
+pub fn write_svg(
+    file_name: &str,
+    grid: &Grid,
+    rgba: &[u8],
+    background: Color,
+    shape: Shape,
+    merge: bool,
+    scale: f64,
+) -> std::io::Result<()> {
+    // horizontal runs (x, y, length) of particles with the same color, by color
+    let mut runs: BTreeMap<[u8; 3], Vec<(usize, usize, usize)>> = BTreeMap::new();
+    for y in 0..grid.height {
+        let mut x = 0;
+        while x < grid.width {
+            let idx = x + y * grid.width;
+            let color = &rgba[idx * 4..idx * 4 + 4];
+            if !grid.filled(idx) || color == background {
+                x += 1;
+                continue;
+            }
+            let mut length = 1;
+            if merge {
+                while x + length < grid.width {
+                    let next = idx + length;
+                    if !grid.filled(next) || &rgba[next * 4..next * 4 + 4] != color {
+                        break;
+                    }
+                    length += 1;
+                }
+            }
+            runs.entry([color[0], color[1], color[2]])
+                .or_default()
+                .push((x, y, length));
+            x += length;
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(file_name)?);
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        grid.width as f64 * scale,
+        grid.height as f64 * scale,
+        grid.width,
+        grid.height
+    )?;
+    writeln!(
+        writer,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        grid.width,
+        grid.height,
+        hex(&background)
+    )?;
+    for (color, runs) in runs.iter() {
+        if merge {
+            let cap = match shape {
+                Shape::Circle => "round",
+                Shape::Square => "square",
+            };
+            writeln!(
+                writer,
+                r#"<path fill="none" stroke="{}" stroke-width="1" stroke-linecap="{}" d="{}"/>"#,
+                hex(color),
+                cap,
+                run_path(runs)
+            )?;
+        } else {
+            writeln!(writer, r#"<g fill="{}">"#, hex(color))?;
+            for (x, y, _) in runs.iter() {
+                match shape {
+                    Shape::Circle => {
+                        writeln!(writer, r#"<circle cx="{}.5" cy="{}.5" r="0.5"/>"#, x, y)?
+                    }
+                    Shape::Square => writeln!(
+                        writer,
+                        r#"<rect x="{}" y="{}" width="1" height="1"/>"#,
+                        x, y
+                    )?,
+                }
+            }
+            writeln!(writer, "</g>")?;
+        }
+    }
+    writeln!(writer, "</svg>")?;
+    writer.flush()
+}
+
+/// Path data with a line through the centers of each run. Caps extend each line half a cell past its ends
+fn run_path(runs: &[(usize, usize, usize)]) -> String {
+    let mut d = String::new();
+    for &(x, y, length) in runs.iter() {
+        // writing to a String can't fail
+        let _ = write!(d, "M{}.5 {}.5h{}", x, y, length - 1);
+    }
+    d
+}
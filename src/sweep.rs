@@ -0,0 +1,512 @@
+//! Parameter sweeps: grow an aggregate for every combination of a set of parameter values
+//!
+//! A sweep is described by a manifest file, in TOML (or JSON if the file name ends in .json). Each parameter can be a
+//! single value, a list of values, or a range with a step:
+//!
+//! ```toml
+//! seed = 1     # optional. Run i uses seed + i, so any run can be grown again on its own with --seed
+//! repeats = 3  # optional. Runs per combination of parameters
+//!
+//! [parameters]
+//! width = [200, 400]
+//! particles = 5000
+//! grid_type = ["center", "fourdots"]
+//! stickiness = { from = 0.25, to = 1.0, step = 0.25 }
+//! ```
+//!
+//! The parameters are the simulation flags: width, height, particles, grid_type, spawn_radius, stickiness,
+//! noise_reduction, temperature, max_walk and evaporation. Anything not in the manifest uses its default.
+//!
+//! Everything goes in the output directory:
+//! - `manifest.json`: the manifest, with the seed filled in if it wasn't given
+//! - `index.csv`: one row per finished run, with its parameters and where its files are
+//! - `grids/`: the grid of each run, named by run number (gzipped, like --to-file)
+//! - `images/`: a PNG of each run as drawn on screen
+//!
+//! Rerunning a sweep into the same directory picks up where it left off. Runs already in the index are skipped, and
+//! anything a run left behind before it finished is redone.
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::config::{DlaConfig, GridType};
+use crate::dla::Dla;
+use crate::image;
+
+/// Parameters a manifest can sweep over
+const PARAMETERS: [&str; 10] = [
+    "width",
+    "height",
+    "particles",
+    "grid_type",
+    "spawn_radius",
+    "stickiness",
+    "noise_reduction",
+    "temperature",
+    "max_walk",
+    "evaporation",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default = "default_repeats")]
+    pub repeats: usize,
+    /// Values of each parameter. Sorted by name, so combinations always come out in the same order
+    pub parameters: BTreeMap<String, Values>,
+}
+
+fn default_repeats() -> usize {
+    1
+}
+
+/// The values a parameter takes in a sweep
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Values {
+    /// from, from + step, ... up to and including to
+    Range {
+        from: f64,
+        to: f64,
+        step: f64,
+    },
+    List(Vec<Value>),
+    Single(Value),
+}
+
+impl Values {
+    fn expand(&self) -> Result<Vec<Value>, String> {
+        match self {
+            Values::Range { from, to, step } => {
+                if *step <= 0.0 || to < from {
+                    return Err(format!(
+                        "range from {} to {} needs a positive step and to >= from",
+                        from, to
+                    ));
+                }
+                // a little slack so floating point error doesn't drop the last value
+                let count = ((to - from) / step + 1e-9).floor() as usize + 1;
+                Ok((0..count)
+                    .map(|i| {
+                        let value = from + i as f64 * step;
+                        // keep whole numbers whole so they work for integer parameters and print nicely
+                        if value.fract() == 0.0 {
+                            Value::from(value as i64)
+                        } else {
+                            Value::from(value)
+                        }
+                    })
+                    .collect())
+            }
+            Values::List(values) => Ok(values.clone()),
+            Values::Single(value) => Ok(vec![value.clone()]),
+        }
+    }
+}
+
+impl Manifest {
+    /// Read a manifest. Files ending in .json are JSON, anything else is TOML
+    pub fn from_file(file_name: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|err| format!("couldn't read {}: {}", file_name, err))?;
+        if file_name.to_ascii_lowercase().ends_with(".json") {
+            serde_json::from_str(&text).map_err(|err| err.to_string())
+        } else {
+            toml::from_str(&text).map_err(|err| err.to_string())
+        }
+    }
+
+    /// Every combination of parameter values, repeated `repeats` times. Each is a list of (parameter, value)
+    fn combinations(&self) -> Result<Vec<Vec<(String, Value)>>, String> {
+        let mut combinations: Vec<Vec<(String, Value)>> = vec![Vec::new()];
+        for (name, values) in self.parameters.iter() {
+            if !PARAMETERS.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown parameter '{}'. Options are: {}",
+                    name,
+                    PARAMETERS.join(", ")
+                ));
+            }
+            let values = values
+                .expand()
+                .map_err(|err| format!("parameter '{}': {}", name, err))?;
+            if values.is_empty() {
+                return Err(format!("parameter '{}' has no values", name));
+            }
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((name.clone(), value.clone()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+        Ok(combinations
+            .into_iter()
+            .flat_map(|combination| std::iter::repeat_n(combination, self.repeats))
+            .collect())
+    }
+}
+
+/// Set one parameter of `config`
+fn apply(config: &mut DlaConfig, name: &str, value: &Value) -> Result<(), String> {
+    let error = || format!("parameter '{}' can't be {}", name, value);
+    // ranges produce floats, so whole floats count as integers
+    let integer = || -> Result<u64, String> {
+        match value.as_u64() {
+            Some(n) => Ok(n),
+            None => match value.as_f64() {
+                Some(f) if f >= 0.0 && f.fract() == 0.0 => Ok(f as u64),
+                _ => Err(error()),
+            },
+        }
+    };
+    let float = || value.as_f64().ok_or_else(error);
+    match name {
+        "width" => config.width = integer()? as u32,
+        "height" => config.height = Some(integer()? as u32),
+        "particles" => config.particles = integer()? as usize,
+        "grid_type" => {
+            let name = value.as_str().ok_or_else(error)?;
+            if GridType::from_name(name).is_none() {
+                return Err(format!("unknown grid_type '{}'", name));
+            }
+            config.grid_type = Some(name.to_string());
+        }
+        "spawn_radius" => config.spawn_radius = Some(integer()? as usize),
+        "stickiness" => config.stickiness = Some(float()?),
+        "noise_reduction" => config.noise_reduction = Some(integer()? as u32),
+        "temperature" => config.temperature = Some(float()?),
+        "max_walk" => config.max_walk = Some(integer()?),
+        "evaporation" => config.evaporation = Some(float()?),
+        _ => return Err(error()),
+    }
+    Ok(())
+}
+
+/// How a value is written in the index
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Run every combination in the manifest, writing results to `out_dir`, with up to `threads` runs at a time
+pub fn run_sweep(manifest_file: &str, out_dir: &str, threads: usize) -> Result<(), String> {
+    let mut manifest = Manifest::from_file(manifest_file)?;
+    let combinations = manifest.combinations()?;
+    let names: Vec<String> = manifest.parameters.keys().cloned().collect();
+    let mut configs = Vec::with_capacity(combinations.len());
+    for combination in combinations.iter() {
+        let mut config = DlaConfig::default();
+        for (name, value) in combination.iter() {
+            apply(&mut config, name, value)?;
+        }
+        config.validate()?;
+        configs.push(config);
+    }
+
+    let out = Path::new(out_dir);
+    for dir in [out.to_path_buf(), out.join("grids"), out.join("images")] {
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("couldn't create {}: {}", dir.display(), err))?;
+    }
+
+    // a directory belongs to one sweep. The saved manifest has the seed, so a resumed sweep grows the same runs
+    let saved_manifest = out.join("manifest.json");
+    if saved_manifest.exists() {
+        let saved = Manifest::from_file(&saved_manifest.to_string_lossy())?;
+        let same_seed = manifest.seed.is_none() || manifest.seed == saved.seed;
+        if !same_seed
+            || saved.parameters != manifest.parameters
+            || saved.repeats != manifest.repeats
+        {
+            return Err(format!(
+                "{} holds a different sweep. Use another output directory",
+                out_dir
+            ));
+        }
+        manifest.seed = saved.seed;
+    } else {
+        manifest.seed = Some(manifest.seed.unwrap_or_else(rand::random));
+        let json = serde_json::to_string_pretty(&manifest).map_err(|err| err.to_string())?;
+        fs::write(&saved_manifest, json)
+            .map_err(|err| format!("couldn't write {}: {}", saved_manifest.display(), err))?;
+    }
+    let base_seed = manifest.seed.unwrap();
+
+    let index_path = out.join("index.csv");
+    let header = format!(
+        "run,seed,{},stuck_particles,updates,wall_time,grid,image",
+        names.join(",")
+    );
+    let finished = read_index(&index_path, &header)?;
+    let pending: Vec<usize> = (0..configs.len())
+        .filter(|run| !finished.contains(run))
+        .collect();
+    eprintln!(
+        "{}{}{}{}{}{}",
+        "Runs: ".to_string().bold().blue(),
+        configs.len().to_string().green(),
+        "   Already done: ".to_string().bold().blue(),
+        finished.len().to_string().green(),
+        "   Base seed: ".to_string().bold().blue(),
+        base_seed.to_string().green()
+    );
+
+    let index = Mutex::new(
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&index_path)
+            .map_err(|err| format!("couldn't open {}: {}", index_path.display(), err))?,
+    );
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let error: Mutex<Option<String>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, pending.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= pending.len() || error.lock().unwrap().is_some() {
+                    break;
+                }
+                let run = pending[i];
+                let seed = base_seed.wrapping_add(run as u64);
+                let row = match grow(&configs[run], run, seed, out) {
+                    Ok((stuck, updates, wall_time, grid, image)) => {
+                        let values: Vec<String> =
+                            combinations[run].iter().map(|(_, v)| cell(v)).collect();
+                        format!(
+                            "{},{},{},{},{},{:.3},{},{}\n",
+                            run,
+                            seed,
+                            values.join(","),
+                            stuck,
+                            updates,
+                            wall_time,
+                            grid,
+                            image
+                        )
+                    }
+                    Err(err) => {
+                        *error.lock().unwrap() = Some(err);
+                        break;
+                    }
+                };
+                // the row only goes in once everything it points at is written, and in one write
+                let mut index = index.lock().unwrap();
+                if let Err(err) = index.write_all(row.as_bytes()).and_then(|_| index.flush()) {
+                    *error.lock().unwrap() = Some(format!("couldn't write to the index: {}", err));
+                    break;
+                }
+                std::mem::drop(index);
+
+                let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                eprintln!(
+                    "{}{}{}{}",
+                    "Finished run: ".to_string().bold().blue(),
+                    run.to_string().green(),
+                    "   Remaining: ".to_string().bold().blue(),
+                    (pending.len() - done).to_string().green()
+                );
+            });
+        }
+    });
+
+    match error.into_inner().unwrap() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Grow one run and save its grid and image. Returns (stuck particles, updates, seconds, grid file, image file) with
+/// the files relative to the output directory
+fn grow(
+    config: &DlaConfig,
+    run: usize,
+    seed: u64,
+    out: &Path,
+) -> Result<(usize, u64, f64, String, String), String> {
+    let mut config = config.clone();
+    config.seed = Some(seed);
+
+    let now = Instant::now();
+    let mut sim = Dla::from(&config);
+    sim.run();
+    let wall_time = now.elapsed().as_secs_f64();
+
+    // an interrupted run may have left files behind, and gzip won't overwrite them
+    let grid_name = format!("grids/{:05}", run);
+    let grid_path: PathBuf = out.join(&grid_name);
+    let grid_gz = out.join(format!("{}.gz", grid_name));
+    for stale in [&grid_path, &grid_gz] {
+        if stale.exists() {
+            fs::remove_file(stale)
+                .map_err(|err| format!("couldn't remove {}: {}", stale.display(), err))?;
+        }
+    }
+    sim.grid()
+        .to_file(&grid_path.to_string_lossy(), &sim.parameters());
+    if !grid_gz.exists() {
+        return Err(format!("couldn't write {}", grid_gz.display()));
+    }
+
+    let image_name = format!("images/{:05}.png", run);
+    let (width, height) = sim.size();
+    let frame = sim.render();
+    let text = sim.image_text();
+    image::write_png(
+        &out.join(&image_name).to_string_lossy(),
+        width,
+        height,
+        &frame,
+        1,
+        &text,
+    )
+    .map_err(|err| format!("couldn't write {}: {}", image_name, err))?;
+
+    Ok((
+        sim.stuck_particles(),
+        sim.updates(),
+        wall_time,
+        format!("{}.gz", grid_name),
+        image_name,
+    ))
+}
+
+/// Returns the runs already in the index. Creates the index if it doesn't exist, and drops any row that was cut off
+/// when a sweep was interrupted
+fn read_index(path: &Path, header: &str) -> Result<HashSet<usize>, String> {
+    let mut finished = HashSet::new();
+    let mut rows = vec![header.to_string()];
+    if path.exists() {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        let mut lines = text.lines();
+        if lines.next() != Some(header) {
+            return Err(format!(
+                "{} doesn't match this sweep's columns",
+                path.display()
+            ));
+        }
+        let columns = header.split(',').count();
+        // rows are written whole with their newline, so a last line without one was cut off
+        let mut rows_in_file: Vec<&str> = lines.collect();
+        if !text.ends_with('\n') {
+            rows_in_file.pop();
+        }
+        for line in rows_in_file {
+            let complete = line.split(',').count() == columns;
+            if let (true, Some(Ok(run))) = (complete, line.split(',').next().map(str::parse)) {
+                finished.insert(run);
+                rows.push(line.to_string());
+            }
+        }
+    }
+    let mut text = rows.join("\n");
+    text.push('\n');
+    fs::write(path, text).map_err(|err| format!("couldn't write {}: {}", path.display(), err))?;
+    Ok(finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for a test to write into
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dla-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn toml_and_json_agree() {
+        let dir = scratch("formats");
+        let toml = dir.join("sweep.toml");
+        let json = dir.join("sweep.json");
+        fs::write(
+            &toml,
+            "repeats = 2\n\n[parameters]\nwidth = [20, 30]\nparticles = 10\n\
+             stickiness = { from = 0.5, to = 1.0, step = 0.25 }\n",
+        )
+        .unwrap();
+        fs::write(
+            &json,
+            r#"{"repeats": 2, "parameters": {"width": [20, 30], "particles": 10,
+                "stickiness": {"from": 0.5, "to": 1.0, "step": 0.25}}}"#,
+        )
+        .unwrap();
+
+        let toml = Manifest::from_file(&toml.to_string_lossy()).unwrap();
+        let json = Manifest::from_file(&json.to_string_lossy()).unwrap();
+        assert_eq!(toml, json);
+        let combinations = toml.combinations().unwrap();
+        assert_eq!(combinations, json.combinations().unwrap());
+
+        // 3 stickiness values x 2 widths, each twice, sorted by parameter name
+        assert_eq!(combinations.len(), 12);
+        let first: Vec<String> = combinations[0]
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        assert_eq!(first, ["particles=10", "stickiness=0.5", "width=20"]);
+        assert_eq!(combinations[0], combinations[1]);
+        assert_eq!(combinations[11][1].1, Value::from(1));
+        assert_eq!(combinations[11][2].1, Value::from(30));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resume() {
+        let dir = scratch("resume");
+        let manifest = dir.join("sweep.toml");
+        fs::write(
+            &manifest,
+            "seed = 7\n\n[parameters]\nwidth = 20\nparticles = [5, 10]\n",
+        )
+        .unwrap();
+        let manifest = manifest.to_string_lossy();
+        let out = dir.join("out");
+        let out_dir = out.to_string_lossy();
+        run_sweep(&manifest, &out_dir, 2).unwrap();
+        let index = fs::read_to_string(out.join("index.csv")).unwrap();
+        assert_eq!(index.lines().count(), 3);
+
+        // drop run 1 from the index and the grid of run 0. Only run 1 is grown again
+        let kept: Vec<&str> = index.lines().filter(|row| !row.starts_with("1,")).collect();
+        fs::write(out.join("index.csv"), kept.join("\n") + "\n").unwrap();
+        fs::remove_file(out.join("grids/00000.gz")).unwrap();
+        run_sweep(&manifest, &out_dir, 2).unwrap();
+        let index = fs::read_to_string(out.join("index.csv")).unwrap();
+        assert_eq!(index.lines().count(), 3);
+        assert!(index.lines().any(|row| row.starts_with("1,8,")));
+        assert!(!out.join("grids/00000.gz").exists());
+        assert!(out.join("grids/00001.gz").exists());
+
+        // a different sweep can't reuse the directory
+        let changed = dir.join("changed.toml");
+        fs::write(
+            &changed,
+            "seed = 7\n\n[parameters]\nwidth = 20\nparticles = [5, 20]\n",
+        )
+        .unwrap();
+        let err = run_sweep(&changed.to_string_lossy(), &out_dir, 1).unwrap_err();
+        assert!(err.contains("different sweep"), "{}", err);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
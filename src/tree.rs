@@ -0,0 +1,245 @@
+//! The aggregation tree: which particle each particle stuck to
+//!
+//! Every particle that sticks has a parent, the filled neighbor it attached to. Following parents leads back to the
+//! seed, so the aggregate forms a tree. All seeds are treated as a single root, since they're all there before
+//! anything sticks (and all have id 0).
+use std::collections::HashMap;
+
+use crate::grid::Grid;
+
+/// Returns the grid index of the parent of the particle at `idx`, or None if it doesn't touch an older particle.
+///
+/// The parent is the filled 8-neighbor with the lowest id that's lower than the particle's own id. Ids increase in the
+/// order particles stuck, so this is the oldest particle it touches. Ties (only possible between seeds, which all have
+/// id 0) go to the first neighbor in row-major order. Since parents are always older than their children, following
+/// parents can never loop.
+pub fn parent_of(grid: &Grid, idx: usize) -> Option<usize> {
+    let (x, y) = ((idx % grid.width) as isize, (idx / grid.width) as isize);
+    let id = grid.cells[idx].id;
+
+    let mut parent: Option<usize> = None;
+    for ny in y - 1..=y + 1 {
+        for nx in x - 1..=x + 1 {
+            if (nx, ny) == (x, y)
+                || nx < 0
+                || ny < 0
+                || nx >= grid.width as isize
+                || ny >= grid.height as isize
+            {
+                continue;
+            }
+            let n_idx = nx as usize + ny as usize * grid.width;
+            let neighbor = &grid.cells[n_idx];
+            if neighbor.filled
+                && neighbor.id < id
+                && parent.is_none_or(|p| neighbor.id < grid.cells[p].id)
+            {
+                parent = Some(n_idx);
+            }
+        }
+    }
+    parent
+}
+
+/// A rooted tree over the particles of a grid. Node 0 is the root, standing in for all of the seeds. Nodes 1.. are the
+/// particles, in the order they stuck.
+#[derive(Clone, Debug)]
+pub struct AggregationTree {
+    /// Particle id of each node. The root's is 0
+    pub ids: Vec<usize>,
+    /// Grid index of each node. The root doesn't have one
+    pub cells: Vec<Option<usize>>,
+    /// Parent node of each node. Only the root doesn't have one
+    pub parents: Vec<Option<usize>>,
+    pub children: Vec<Vec<usize>>,
+    /// Number of links between each node and the root. This is the chemical distance to the seed
+    pub depths: Vec<usize>,
+    /// Number of nodes in the subtree under each node, including itself
+    pub subtree_sizes: Vec<usize>,
+}
+
+impl AggregationTree {
+    /// Build the tree of the particles in `grid`.
+    ///
+    /// If `recorded` is given, it maps each particle id to its parent's id, as recorded while the simulation ran.
+    /// Otherwise parents are inferred from the grid with parent_of, which gives the same result unless particles
+    /// moved after sticking. Particles without a parent in the grid (their parent detached) hang off the root.
+    pub fn from_grid(grid: &Grid, recorded: Option<&[Option<usize>]>) -> Self {
+        let mut particles: Vec<(usize, usize)> = grid
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| cell.filled && cell.id != 0)
+            .map(|(idx, cell)| (cell.id, idx))
+            .collect();
+        particles.sort_unstable();
+
+        let mut ids = vec![0];
+        let mut cells = vec![None];
+        let mut node_of_id: HashMap<usize, usize> = HashMap::new();
+        node_of_id.insert(0, 0);
+        for (id, idx) in particles.iter() {
+            node_of_id.insert(*id, ids.len());
+            ids.push(*id);
+            cells.push(Some(*idx));
+        }
+
+        let mut parents = vec![None];
+        for (id, idx) in particles.iter() {
+            let parent_id = match recorded {
+                Some(recorded) => recorded.get(*id).copied().flatten(),
+                None => parent_of(grid, *idx).map(|p| grid.cells[p].id),
+            };
+            let parent = parent_id
+                .and_then(|parent_id| node_of_id.get(&parent_id).copied())
+                .unwrap_or(0);
+            parents.push(Some(parent));
+        }
+
+        let mut children = vec![Vec::new(); ids.len()];
+        let mut depths = vec![0; ids.len()];
+        // parents are always older than their children, so in id order a parent's depth is known before its children's
+        for node in 1..ids.len() {
+            let parent = parents[node].unwrap();
+            children[parent].push(node);
+            depths[node] = depths[parent] + 1;
+        }
+        // and the other way around for subtree sizes
+        let mut subtree_sizes = vec![1; ids.len()];
+        for node in (1..ids.len()).rev() {
+            let parent = parents[node].unwrap();
+            subtree_sizes[parent] += subtree_sizes[node];
+        }
+
+        Self {
+            ids,
+            cells,
+            parents,
+            children,
+            depths,
+            subtree_sizes,
+        }
+    }
+
+    /// Number of particles in the tree, not counting the root
+    pub fn particles(&self) -> usize {
+        self.ids.len() - 1
+    }
+
+    /// Branch tips: particles nothing stuck to
+    pub fn tips(&self) -> Vec<usize> {
+        (1..self.ids.len())
+            .filter(|node| self.children[*node].is_empty())
+            .collect()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.depths.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn mean_depth(&self) -> f64 {
+        if self.particles() == 0 {
+            return 0.0;
+        }
+        self.depths[1..].iter().sum::<usize>() as f64 / self.particles() as f64
+    }
+
+    /// Write one row per particle: id, position, parent id, depth and subtree size
+    pub fn to_csv(&self, grid: &Grid, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        writeln!(writer, "id,x,y,parent,depth,subtree_size")?;
+        for node in 1..self.ids.len() {
+            let idx = self.cells[node].unwrap();
+            let parent = self.parents[node].unwrap();
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                self.ids[node],
+                idx % grid.width,
+                idx / grid.width,
+                self.ids[parent],
+                self.depths[node],
+                self.subtree_sizes[node]
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Particle;
+
+    /// A 5x5 grid with a seed in the middle and (x, y, id) particles
+    fn grid(particles: &[(usize, usize, usize)]) -> Grid {
+        let mut cells = vec![
+            Particle {
+                filled: false,
+                id: 0
+            };
+            25
+        ];
+        cells[12].filled = true;
+        for &(x, y, id) in particles {
+            cells[x + y * 5] = Particle { filled: true, id };
+        }
+        Grid {
+            cells,
+            width: 5,
+            height: 5,
+        }
+    }
+
+    #[test]
+    fn parent_is_the_oldest_older_neighbor() {
+        let grid = grid(&[(3, 2, 1), (3, 1, 2), (2, 1, 3), (1, 1, 4)]);
+        // touches the seed and particle 1, the seed is older
+        assert_eq!(parent_of(&grid, 3 + 5), Some(12));
+        // touches particles 1, 2 and 4 (and the seed). 4 is younger, so it's the seed
+        assert_eq!(parent_of(&grid, 2 + 5), Some(12));
+        // particle 4 touches 3 and the seed
+        assert_eq!(parent_of(&grid, 1 + 5), Some(12));
+        // the seed itself has nothing older
+        assert_eq!(parent_of(&grid, 12), None);
+
+        // away from the seed, the lowest older id wins over the closer one
+        let grid = self::grid(&[(3, 2, 1), (4, 1, 2), (4, 0, 3), (3, 0, 4)]);
+        assert_eq!(parent_of(&grid, 3), Some(4 + 5)); // touches 2 and 3, not the seed
+        assert_eq!(parent_of(&grid, 4), Some(4 + 5));
+    }
+
+    #[test]
+    fn seeds_are_the_root() {
+        // a second seed, and particles stuck to each
+        let mut grid = grid(&[(1, 2, 1), (3, 0, 2), (4, 0, 3)]);
+        grid.cells[2].filled = true; // (2, 0)
+        let tree = AggregationTree::from_grid(&grid, None);
+        assert_eq!(tree.ids, [0, 1, 2, 3]);
+        assert_eq!(tree.parents, [None, Some(0), Some(0), Some(2)]);
+        assert_eq!(tree.children[0], [1, 2]);
+        assert_eq!(tree.depths, [0, 1, 1, 2]);
+        assert_eq!(tree.subtree_sizes, [4, 1, 2, 1]);
+        assert_eq!(tree.tips(), [1, 3]);
+    }
+
+    #[test]
+    fn recorded_parents_win() {
+        // particle 3 touches 1 and 2, so the grid says 1
+        let grid = grid(&[(1, 2, 1), (1, 1, 2), (0, 1, 3)]);
+        let inferred = AggregationTree::from_grid(&grid, None);
+        assert_eq!(inferred.parents[3], Some(1));
+
+        // but it was recorded sticking to 2
+        let recorded = [None, Some(0), Some(1), Some(2)];
+        let tree = AggregationTree::from_grid(&grid, Some(&recorded));
+        assert_eq!(tree.parents, [None, Some(0), Some(1), Some(2)]);
+        assert_eq!(tree.depths, [0, 1, 2, 3]);
+
+        // a parent that isn't in the grid any more leaves the particle on the root
+        let recorded = [None, Some(0), Some(9), None];
+        let tree = AggregationTree::from_grid(&grid, Some(&recorded));
+        assert_eq!(tree.parents, [None, Some(0), Some(0), Some(0)]);
+    }
+}